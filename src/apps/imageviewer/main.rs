@@ -2,51 +2,37 @@
 
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Condvar, Mutex};
-use std::thread::JoinHandle;
+use std::sync::Arc;
 
 use bytes::Bytes;
-use iced_widget::core::{Background, Color, ContentFit, Length, Padding, Shadow, border, font};
+use iced_widget::core::keyboard::key::Named;
+use iced_widget::core::{
+    Background, Color, ContentFit, Event, Length, Padding, Shadow, border, font, touch,
+};
+use iced_widget::runtime::futures::event;
 use iced_widget::runtime::{Program, Task};
 use iced_widget::{
     button, column, container, image as iced_image, row, scrollable, slider, stack, text,
 };
 use image::{EncodableLayout, RgbaImage};
+use serde::{Deserialize, Serialize};
 
-use helenos_iced::{Element, ProgramExt, WindowOptions};
-
-#[derive(Debug)]
-enum WorkerJob {
-    None,
-    Resize(Arc<RgbaImage>, f32),
-    Stop,
-}
+use helenos_iced::gesture::Pinch;
+use helenos_iced::shortcut::{Binding, Shortcut};
+use helenos_iced::thread;
+use helenos_iced::worker::Worker;
+use helenos_iced::{Element, ProgramExt, SendMsgFn, Subscription, WindowOptions, error, warn};
 
-fn worker(pair: Arc<(Mutex<WorkerJob>, Condvar)>, send_msg: Box<dyn Fn(Message)>) {
-    let (lock, cvar) = &*pair;
-    let mut guard = lock.lock().unwrap();
-    loop {
-        let (img, zoom) = match &*guard {
-            WorkerJob::None => {
-                guard = cvar.wait(guard).unwrap();
-                continue;
-            }
-            WorkerJob::Resize(img, zoom) => (img.clone(), *zoom),
-            WorkerJob::Stop => break,
-        };
-        *guard = WorkerJob::None;
-        drop(guard);
-        let new_img = image::imageops::resize(
-            &*img,
-            (img.width() as f32 * zoom) as u32,
-            (img.height() as f32 * zoom) as u32,
-            image::imageops::FilterType::Lanczos3,
-        )
-        .into_raw()
-        .into();
-        send_msg(Message::ResizeFinished(zoom, new_img));
-        guard = lock.lock().unwrap();
-    }
+fn resize(image: &RgbaImage, zoom: f32, version: u64) -> Message {
+    let new_img = image::imageops::resize(
+        image,
+        (image.width() as f32 * zoom) as u32,
+        (image.height() as f32 * zoom) as u32,
+        image::imageops::FilterType::Lanczos3,
+    )
+    .into_raw()
+    .into();
+    Message::ResizeFinished(version, zoom, new_img)
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +42,7 @@ struct ImageInfo {
     image: Arc<RgbaImage>,
     bytes: Bytes,
     name: String,
+    path: PathBuf,
     // current zoom of the image
     current_image_zoom: f32,
     // zoom displayed on slider, but the image is not yet resized
@@ -93,11 +80,30 @@ enum State {
     ViewingImage(ImageInfo),
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    folder: Option<PathBuf>,
+    zoom: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            folder: None,
+            zoom: 1.0,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Viewer {
-    worker_pair: Arc<(Mutex<WorkerJob>, Condvar)>,
-    worker_handle: Mutex<Option<JoinHandle<()>>>,
+    // only the zoom picked last matters, so the resizes coalesce
+    resizer: Worker<(Arc<RgbaImage>, f32), Message>,
     state: State,
+    // zoom picked last, used for newly opened images
+    zoom: f32,
+    pinch: Pinch,
 }
 
 impl Program for Viewer {
@@ -115,13 +121,56 @@ impl Program for Viewer {
 }
 
 impl ProgramExt for Viewer {
-    fn stop(&self) {
-        let (lock, cvar) = &*self.worker_pair;
-        *lock.lock().unwrap() = WorkerJob::Stop;
-        cvar.notify_all();
-        if let Some(handle) = self.worker_handle.lock().unwrap().take() {
-            handle.join().unwrap();
-        }
+    type Settings = Settings;
+
+    fn settings(&self) -> Option<Settings> {
+        let folder = match &self.state {
+            State::ChoosingImage { folder, .. } => folder.clone(),
+            State::ViewingImage(img) => img.path.parent()?.to_path_buf(),
+        };
+        Some(Settings {
+            folder: Some(folder),
+            zoom: self.zoom,
+        })
+    }
+
+    fn shortcuts(&self) -> Vec<Binding<Message>> {
+        let State::ViewingImage(img) = &self.state else {
+            return Vec::new();
+        };
+        let zoom = |factor: f32| {
+            let zoom = (img.pending_zoom * factor).clamp(0.05, max_zoom(img.width, img.height));
+            Message::ZoomChanged(zoom)
+        };
+        vec![
+            Binding::new(
+                Shortcut::named(Named::ArrowLeft),
+                "Previous image",
+                Message::PreviousImage,
+            ),
+            Binding::new(
+                Shortcut::named(Named::ArrowRight),
+                "Next image",
+                Message::NextImage,
+            ),
+            Binding::new(Shortcut::char('+'), "Zoom in", zoom(ZOOM_STEP)),
+            // where `+` needs Shift, it is on the `=` key
+            Binding::new(Shortcut::char('='), "Zoom in", zoom(ZOOM_STEP)),
+            Binding::new(Shortcut::char('-'), "Zoom out", zoom(1.0 / ZOOM_STEP)),
+            Binding::new(
+                Shortcut::named(Named::Escape),
+                "Close the image",
+                Message::ImageClosed,
+            ),
+        ]
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        // the scrollable captures the touches, so the captured events are listened to as well
+        event::listen_with(|event, _status, _window| match event {
+            Event::Touch(event) => Some(Message::Touch(event)),
+            _ => None,
+        })
     }
 }
 
@@ -131,15 +180,21 @@ enum Message {
     SubfolderSelected(String),
     SubfolderUp,
     ImageSelected(String),
+    ImageLoaded(Result<ImageInfo, String>),
+    CopyPath,
     ZoomChanged(f32),
-    ResizeFinished(f32, Bytes),
+    PreviousImage,
+    NextImage,
+    // version of the resizer, so that resizes of the previous image are ignored
+    ResizeFinished(u64, f32, Bytes),
+    Touch(touch::Event),
 }
 
 fn list_folder(folder: &Path) -> Vec<FolderItem> {
     let items = match std::fs::read_dir(folder) {
         Ok(i) => i,
         Err(e) => {
-            eprintln!("Error reading directory: {e}");
+            error!("Error reading directory: {e}");
             return vec![];
         }
     };
@@ -165,6 +220,27 @@ fn list_folder(folder: &Path) -> Vec<FolderItem> {
     items
 }
 
+/// Loads the image before or after this one in its folder, in the order of the chooser,
+/// wrapping around at the ends
+fn load_adjacent_image(path: &Path, step: isize) -> Task<Message> {
+    let Some(folder) = path.parent() else {
+        return Task::none();
+    };
+    let images: Vec<PathBuf> = list_folder(folder)
+        .into_iter()
+        .filter(|item| !item.is_dir && image::ImageFormat::from_path(&item.name).is_ok())
+        .map(|item| folder.join(item.name))
+        .collect();
+    let Some(i) = images.iter().position(|image| image == path) else {
+        return Task::none();
+    };
+    let next = images[(i as isize + step).rem_euclid(images.len() as isize) as usize].clone();
+    Task::perform(
+        thread::spawn_blocking(move || load_image(&next)),
+        Message::ImageLoaded,
+    )
+}
+
 fn load_image(path: &Path) -> Result<ImageInfo, String> {
     let name = path.file_name().map_or_else(
         || "Error opening file: missing filename".to_owned(),
@@ -181,9 +257,23 @@ fn load_image(path: &Path) -> Result<ImageInfo, String> {
         current_image_zoom: 1.0,
         pending_zoom: 1.0,
         name,
+        path: path.to_path_buf(),
     })
 }
 
+/// Largest zoom offered by the slider: up to 2x, as long as the image fits into 800x800 pixels,
+/// but always at least 1x
+fn max_zoom(width: u32, height: u32) -> f32 {
+    let max_width = 800.0;
+    let max_height = 800.0;
+    (2.0_f32)
+        .minimum(max_width / width as f32)
+        .minimum(max_height / height as f32)
+        .maximum(1.0)
+}
+
+// zoom factor of one press of the zoom shortcuts
+const ZOOM_STEP: f32 = 1.25;
 const DEFAULT_MSG: &'static str = "Please select an image";
 const MONOSPACE: font::Font = font::Font {
     family: font::Family::Name("Noto Sans Mono"),
@@ -193,7 +283,33 @@ const MONOSPACE: font::Font = font::Font {
 };
 
 impl Viewer {
+    fn request_resize(&self, image: Arc<RgbaImage>, zoom: f32) {
+        self.resizer.submit((image, zoom));
+    }
+
+    /// Shows the image, resized to the last used zoom
+    fn show_image(&mut self, mut image: ImageInfo) {
+        self.resizer.cancel();
+        let zoom = self.zoom.clamp(0.05, max_zoom(image.width, image.height));
+        if zoom != image.current_image_zoom {
+            self.request_resize(image.image.clone(), zoom);
+            image.pending_zoom = zoom;
+        }
+        self.state = State::ViewingImage(image);
+    }
+
     fn update(&mut self, event: Message) -> Task<Message> {
+        if let Message::Touch(event) = event {
+            // pinching zooms like the slider
+            let gesture = self.pinch.update(&event);
+            if let (Some(gesture), State::ViewingImage(img)) = (gesture, &self.state) {
+                let zoom =
+                    (img.pending_zoom * gesture.scale).clamp(0.05, max_zoom(img.width, img.height));
+                return self.update(Message::ZoomChanged(zoom));
+            }
+            return Task::none();
+        }
+
         if let State::ChoosingImage { message, .. } = &mut self.state {
             *message = None;
         }
@@ -204,13 +320,21 @@ impl Viewer {
                 State::ChoosingImage {
                     folder, message, ..
                 },
-            ) => match load_image(&folder.join(&name)) {
+            ) => {
+                *message = Some(format!("Loading {name}..."));
+                let path = folder.join(&name);
+                return Task::perform(
+                    thread::spawn_blocking(move || load_image(&path)),
+                    Message::ImageLoaded,
+                );
+            }
+            (Message::ImageLoaded(result), State::ChoosingImage { message, .. }) => match result {
                 Err(e) => {
-                    eprintln!("{e}");
+                    error!("{e}");
                     *message = Some(e);
                 }
                 Ok(image) => {
-                    self.state = State::ViewingImage(image);
+                    self.show_image(image);
                 }
             },
             (Message::SubfolderSelected(subfolder), State::ChoosingImage { folder, items, .. }) => {
@@ -231,23 +355,35 @@ impl Viewer {
                 *items = list_folder(folder);
             }
             (Message::ZoomChanged(z), State::ViewingImage(img)) => {
-                let (lock, cvar) = &*self.worker_pair;
-                let mut guard = lock.lock().unwrap();
-                if !matches!(&*guard, WorkerJob::Stop) {
-                    *guard = WorkerJob::Resize(img.image.clone(), z);
-                    cvar.notify_one();
-                }
                 img.pending_zoom = z;
+                let image = img.image.clone();
+                self.request_resize(image, z);
+                self.zoom = z;
+            }
+            (Message::CopyPath, State::ViewingImage(img)) => {
+                return helenos_iced::clipboard::write(img.path.to_string_lossy().into_owned());
+            }
+            (Message::ImageLoaded(result), State::ViewingImage(_)) => match result {
+                Err(e) => error!("{e}"),
+                Ok(image) => self.show_image(image),
+            },
+            (Message::PreviousImage, State::ViewingImage(img)) => {
+                return load_adjacent_image(&img.path, -1);
             }
-            (Message::ResizeFinished(z, bytes), State::ViewingImage(img)) => {
-                img.current_image_zoom = z;
-                img.bytes = bytes;
+            (Message::NextImage, State::ViewingImage(img)) => {
+                return load_adjacent_image(&img.path, 1);
             }
-            (Message::ResizeFinished(_, _), State::ChoosingImage { .. }) => {
+            (Message::ResizeFinished(version, z, bytes), State::ViewingImage(img)) => {
+                if version == self.resizer.version() {
+                    img.current_image_zoom = z;
+                    img.bytes = bytes;
+                }
+            }
+            (Message::ResizeFinished(..), State::ChoosingImage { .. }) => {
                 // ignore
             }
             x => {
-                eprintln!("Incorrect message: {x:?}");
+                warn!("Incorrect message: {x:?}");
             }
         }
         Task::none()
@@ -331,17 +467,13 @@ impl Viewer {
             ..
         }: &ImageInfo,
     ) -> Element<Message> {
-        let max_width = 800.0;
-        let max_height = 800.0;
-        let max_zoom = (2.0_f32)
-            .minimum(max_width / *width as f32)
-            .minimum(max_height / *height as f32)
-            .maximum(1.0);
+        let max_zoom = max_zoom(*width, *height);
         let header = container(
             row![
                 text(name.to_owned()).font(MONOSPACE),
                 slider(0.05..=max_zoom, *pending_zoom, |z| Message::ZoomChanged(z)).step(0.05),
                 text(format!("Zoom: {:.2}x", pending_zoom)).font(MONOSPACE),
+                button("Copy path").on_press(Message::CopyPath).padding(3.0),
                 button("Close image")
                     .on_press(Message::ImageClosed)
                     .padding(3.0),
@@ -374,48 +506,57 @@ impl Viewer {
 
     pub fn new(
         image: Option<impl AsRef<Path>>,
-        send_msg: Box<dyn Fn(Message) + Send + 'static>,
+        send_msg: SendMsgFn<Message>,
+        settings: Settings,
     ) -> Self {
-        let worker_pair = Arc::new((Mutex::new(WorkerJob::None), Condvar::new()));
-        let worker_handle = std::thread::spawn({
-            let pair = worker_pair.clone();
-            move || worker(pair, send_msg)
-        });
-        Self {
-            worker_pair,
-            worker_handle: Mutex::new(Some(worker_handle)),
-            state: match image {
-                Some(image) => match load_image(image.as_ref()) {
-                    Ok(image) => State::ViewingImage(image),
-                    Err(e) => {
-                        eprintln!("{e}");
-                        let folder = image.as_ref().parent().unwrap_or(Path::new("/"));
-                        State::ChoosingImage {
-                            folder: folder.to_path_buf(),
-                            items: list_folder(folder),
-                            message: Some(e),
-                        }
-                    }
-                },
-                None => {
-                    let folder = PathBuf::from("/");
-                    State::ChoosingImage {
-                        items: list_folder(&folder),
-                        folder,
-                        message: None,
-                    }
-                }
+        let resizer =
+            Worker::coalescing(send_msg, |(image, zoom): (Arc<RgbaImage>, f32), context| {
+                Some(resize(&image, zoom, context.version()))
+            });
+        let folder = settings
+            .folder
+            .filter(|folder| folder.is_dir())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let mut viewer = Self {
+            resizer,
+            state: State::ChoosingImage {
+                items: list_folder(&folder),
+                folder,
+                message: None,
             },
+            zoom: settings.zoom,
+            pinch: Pinch::default(),
+        };
+        if let Some(image) = image {
+            match load_image(image.as_ref()) {
+                Ok(image) => viewer.show_image(image),
+                Err(e) => {
+                    error!("{e}");
+                    let folder = image.as_ref().parent().unwrap_or(Path::new("/"));
+                    viewer.state = State::ChoosingImage {
+                        folder: folder.to_path_buf(),
+                        items: list_folder(folder),
+                        message: Some(e),
+                    };
+                }
+            }
         }
+        viewer
     }
 }
 
 fn main() {
-    helenos_iced::run(
-        |create_send_msg| Viewer::new(std::env::args().nth(1), create_send_msg()),
+    if let Err(e) = helenos_iced::run(
+        |create_send_msg, settings| {
+            Viewer::new(std::env::args().nth(1), create_send_msg(), settings)
+        },
         WindowOptions {
             caption: Cow::Borrowed("Image viewer.rs"),
             maximized: false,
+            ..Default::default()
         },
-    );
+    ) {
+        error!("{e}");
+        std::process::exit(1);
+    }
 }
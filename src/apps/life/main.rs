@@ -1,29 +1,27 @@
-#![feature(thread_sleep_until)]
-
 // this application was taken from https://github.com/iced-rs/iced/blob/b1c13e285ee6009a3c547ffb12038b0ca91c4d35/examples/game_of_life/src/main.rs
-// and only small modifications were made to run Ticks in our own thread
+// and only small modifications were made to run the grid computations in our own thread
 // Copyright 2019 Héctor Ramón, Iced contributors
 // this file is licensed under MIT license
 
 use std::borrow::Cow;
-use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::sync::mpsc::{self, TryRecvError};
-use std::sync::{Arc, Condvar, Mutex};
-use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use grid::Grid;
 use iced_runtime::Program;
 use iced_widget::core::Length;
 use iced_widget::core::alignment::Vertical;
+use iced_widget::core::keyboard::key::Named;
 use iced_widget::runtime::Task;
-use iced_widget::{button, checkbox, column, container, pick_list, row, slider, text};
+use iced_widget::{Theme, button, checkbox, column, container, pick_list, row, slider, text};
+use serde::{Deserialize, Serialize};
 
 mod preset;
 use preset::Preset;
 
-use helenos_iced::{Element, ProgramExt, SendMsgFn, WindowOptions};
+use helenos_iced::shortcut::{Binding, Shortcut};
+use helenos_iced::worker::Worker;
+use helenos_iced::{Element, ProgramExt, SendMsgFn, Subscription, WindowOptions, error, time};
 
 impl Program for GameOfLife {
     type Message = Message;
@@ -40,103 +38,80 @@ impl Program for GameOfLife {
 }
 
 impl ProgramExt for GameOfLife {
-    fn stop(&self) {
-        self.worker_sender.send(WorkerMessage::Stop).unwrap();
-        *self.looper_state.0.lock().unwrap() = LooperState::Stop;
-        self.looper_state.1.notify_all();
-
-        if let Some(handles) = self.join_handles.lock().unwrap().take() {
-            for handle in handles {
-                handle.join().unwrap();
-            }
-        }
+    type Settings = Settings;
+
+    fn on_suspend(&mut self) {
+        self.resume_playing = std::mem::take(&mut self.is_playing);
     }
-}
 
-enum WorkerMessage {
-    Stop,
-    Work(Box<dyn FnOnce() -> Option<Message> + Send>),
-}
+    fn on_resume(&mut self) -> Task<Message> {
+        self.is_playing |= std::mem::take(&mut self.resume_playing);
+        Task::none()
+    }
 
-fn worker(worker_receiver: mpsc::Receiver<WorkerMessage>, send_msg: SendMsgFn<Message>) {
-    let mut pending_msgs = VecDeque::new();
-    loop {
-        // iterate all pending messages to find if there is any stop
-        loop {
-            match worker_receiver.try_recv() {
-                Ok(WorkerMessage::Stop) => return,
-                Ok(WorkerMessage::Work(task)) => {
-                    pending_msgs.push_back(task);
-                }
-                Err(TryRecvError::Empty) => break,
-                Err(TryRecvError::Disconnected) => {
-                    panic!("Worker channel disconnected");
-                }
-            }
-        }
-        if pending_msgs.is_empty() {
-            // blocking recv
-            match worker_receiver.recv().unwrap() {
-                WorkerMessage::Stop => return,
-                WorkerMessage::Work(task) => {
-                    pending_msgs.push_back(task);
-                }
-            }
-        }
-        let task = pending_msgs.pop_front().unwrap();
-        if let Some(msg) = task() {
-            send_msg(msg);
+    fn settings(&self) -> Option<Settings> {
+        Some(Settings {
+            speed: self.speed,
+            show_grid_lines: self.grid.are_lines_visible(),
+            preset: self.last_preset,
+        })
+    }
+
+    fn shortcuts(&self) -> Vec<Binding<Message>> {
+        vec![
+            Binding::new(
+                Shortcut::named(Named::Space),
+                if self.is_playing { "Pause" } else { "Play" },
+                Message::TogglePlayback,
+            ),
+            Binding::new(Shortcut::char('n'), "Next generation", Message::Next),
+            Binding::new(Shortcut::char('c'), "Clear the grid", Message::Clear),
+        ]
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        if self.is_playing {
+            time::every(Duration::from_millis(1000 / self.speed as u64)).map(|_| Message::Tick)
+        } else {
+            Subscription::none()
         }
     }
 }
 
-#[derive(Debug)]
-enum LooperState {
-    Paused,
-    Running { speed: usize },
-    Stop,
-}
+// computes the ticks of the grid
+type TickJob = Box<dyn FnOnce() -> grid::Message + Send>;
 
-fn looper(looper_state: Arc<(Mutex<LooperState>, Condvar)>, send_msg: SendMsgFn<Message>) {
-    let mut last_tick = None;
-    let (looper_state, condvar) = &*looper_state;
-    let mut state = looper_state.lock().unwrap();
-    loop {
-        let speed = match *state {
-            LooperState::Paused => {
-                state = condvar.wait(state).unwrap();
-                continue;
-            }
-            LooperState::Stop => return,
-            LooperState::Running { speed } => speed,
-        };
-        drop(state);
-        if let Some(last_tick) = last_tick {
-            std::thread::sleep_until(last_tick + Duration::from_millis(1000 / speed as u64));
-        }
-        last_tick = Some(Instant::now());
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    speed: usize,
+    show_grid_lines: bool,
+    preset: Preset,
+}
 
-        // avoid send_msg if we are being stopped, it causes deadlock (send_msg from HelenOS framework implementation
-        // tries to lock the outer application lock, but it is already held by .stop())
-        state = looper_state.lock().unwrap();
-        if matches!(*state, LooperState::Stop) {
-            return;
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            speed: 5,
+            show_grid_lines: true,
+            preset: Preset::default(),
         }
-        send_msg(Message::Tick);
     }
 }
 
 struct GameOfLife {
     grid: Grid,
     is_playing: bool,
+    // whether to play again when the program is resumed
+    resume_playing: bool,
     queued_ticks: usize,
     speed: usize,
-    version: usize,
+    // the preset picked last, the grid has a custom one once the user changes it
+    last_preset: Preset,
 
-    worker_sender: mpsc::Sender<WorkerMessage>,
-    looper_state: Arc<(Mutex<LooperState>, Condvar)>,
-
-    join_handles: Mutex<Option<[JoinHandle<()>; 2]>>,
+    // its version is increased whenever the grid is replaced, so that the ticks and the
+    // messages of the old grid are ignored
+    worker: Worker<TickJob, Message>,
 }
 
 impl Debug for GameOfLife {
@@ -145,15 +120,14 @@ impl Debug for GameOfLife {
             .field("is_playing", &self.is_playing)
             .field("queued_ticks", &self.queued_ticks)
             .field("speed", &self.speed)
-            .field("version", &self.version)
-            .field("looper_state", &self.looper_state)
+            .field("worker", &self.worker)
             .finish_non_exhaustive()
     }
 }
 
 #[derive(Debug, Clone)]
 enum Message {
-    Grid(grid::Message, usize),
+    Grid(grid::Message, u64),
     Tick,
     TogglePlayback,
     ToggleGrid(bool),
@@ -164,46 +138,30 @@ enum Message {
 }
 
 impl GameOfLife {
-    fn new(create_send_msg: impl Fn() -> SendMsgFn<Message>) -> Self {
-        let (worker_sender, worker_receiver) = mpsc::channel();
-        let worker_handle = std::thread::spawn({
-            let send_msg = create_send_msg();
-            move || worker(worker_receiver, send_msg)
-        });
-        let looper_state = Arc::new((Mutex::new(LooperState::Paused), Condvar::new()));
-        let looper_handle = std::thread::spawn({
-            let looper_state = looper_state.clone();
-            let send_msg = create_send_msg();
-            move || looper(looper_state, send_msg)
+    fn new(create_send_msg: impl Fn() -> SendMsgFn<Message>, settings: Settings) -> Self {
+        let worker = Worker::new(create_send_msg(), |task: TickJob, context| {
+            Some(Message::Grid(task(), context.version()))
         });
+        let mut grid = Grid::default();
+        if settings.preset != grid.preset() {
+            grid = Grid::from_preset(settings.preset, grid.scaling());
+        }
+        grid.toggle_lines(settings.show_grid_lines);
         Self {
-            grid: Grid::default(),
+            grid,
             is_playing: false,
+            resume_playing: false,
             queued_ticks: 0,
-            speed: 5,
-            version: 0,
-            worker_sender,
-            looper_state,
-            join_handles: Mutex::new(Some([worker_handle, looper_handle])),
-        }
-    }
-
-    fn update_looper_state(&mut self) {
-        let mut guard = self.looper_state.0.lock().unwrap();
-        if !matches!(*guard, LooperState::Stop) {
-            if self.is_playing {
-                *guard = LooperState::Running { speed: self.speed };
-            } else {
-                *guard = LooperState::Paused;
-            }
+            speed: settings.speed.clamp(1, 1000),
+            last_preset: settings.preset,
+            worker,
         }
-        self.looper_state.1.notify_all();
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Grid(message, version) => {
-                if version == self.version {
+                if version == self.worker.version() {
                     self.grid.update(message);
                 }
             }
@@ -213,35 +171,28 @@ impl GameOfLife {
                 if let Some(task) = self.grid.tick(self.queued_ticks) {
                     self.queued_ticks = 0;
 
-                    let version = self.version;
-                    self.worker_sender
-                        .send(WorkerMessage::Work(Box::new(move || {
-                            let message = task();
-                            Some(Message::Grid(message, version))
-                        })))
-                        .unwrap();
+                    self.worker.submit(Box::new(task));
                 }
             }
             Message::TogglePlayback => {
                 self.is_playing = !self.is_playing;
-                self.update_looper_state();
             }
             Message::ToggleGrid(show_grid_lines) => {
                 self.grid.toggle_lines(show_grid_lines);
             }
             Message::Clear => {
                 self.grid.clear();
-                self.version += 1;
+                self.worker.cancel();
             }
             Message::SpeedChanged(speed) => {
                 self.speed = speed.round() as usize;
-                if self.is_playing {
-                    self.update_looper_state();
-                }
             }
             Message::PresetPicked(new_preset) => {
+                let show_lines = self.grid.are_lines_visible();
                 self.grid = Grid::from_preset(new_preset, self.grid.scaling());
-                self.version += 1;
+                self.grid.toggle_lines(show_lines);
+                self.last_preset = new_preset;
+                self.worker.cancel();
             }
         }
 
@@ -251,7 +202,7 @@ impl GameOfLife {
     }
 
     fn view(&self) -> Element<Message> {
-        let version = self.version;
+        let version = self.worker.version();
         let controls = self.view_controls();
 
         let content = column![
@@ -285,7 +236,7 @@ impl GameOfLife {
         .align_y(Vertical::Center)
         .spacing(10);
 
-        let version = self.version;
+        let version = self.worker.version();
         let scaling = self.grid.scaling();
         let scaling = row![
             text!("Zoom: ").size(16),
@@ -332,6 +283,7 @@ mod grid {
     use iced_widget::{Canvas, Renderer, Theme};
 
     use crate::Element;
+    use helenos_iced::gesture::Pinch;
 
     use super::Preset;
     use std::collections::{HashMap, HashSet};
@@ -498,18 +450,51 @@ mod grid {
                 position.y / self.scaling + region.y,
             )
         }
+
+        /// Zooms to the scaling, keeping the point at `anchor` from the center in place
+        fn zoom(&self, scaling: f32, anchor: Option<Vector>) -> Message {
+            let old_scaling = self.scaling;
+            let scaling = scaling.clamp(Self::MIN_SCALING, Self::MAX_SCALING);
+
+            let translation = anchor.map(|anchor| {
+                let factor = scaling - old_scaling;
+
+                self.translation
+                    - Vector::new(
+                        anchor.x * factor / (old_scaling * old_scaling),
+                        anchor.y * factor / (old_scaling * old_scaling),
+                    )
+            });
+
+            Message::Scaled(scaling, translation)
+        }
     }
 
     impl canvas::Program<Message> for Grid {
-        type State = Interaction;
+        type State = Controls;
 
         fn update(
             &self,
-            interaction: &mut Interaction,
+            controls: &mut Controls,
             event: Event,
             bounds: Rectangle,
             cursor: mouse::Cursor,
         ) -> (event::Status, Option<Message>) {
+            if let Event::Touch(touch_event) = event {
+                let gesture = controls.pinch.update(&touch_event);
+                if controls.pinch.is_pinching() {
+                    // two fingers zoom around the point between them instead of drawing
+                    let message = gesture.map(|gesture| {
+                        self.zoom(
+                            self.scaling * gesture.scale,
+                            Some(gesture.center - bounds.center()),
+                        )
+                    });
+                    return (event::Status::Captured, message);
+                }
+            }
+
+            let interaction = &mut controls.interaction;
             if let Event::Mouse(mouse::Event::ButtonReleased(_)) = event {
                 *interaction = Interaction::None;
             }
@@ -591,32 +576,13 @@ mod grid {
                             if y < 0.0 && self.scaling > Self::MIN_SCALING
                                 || y > 0.0 && self.scaling < Self::MAX_SCALING
                             {
-                                let old_scaling = self.scaling;
-
-                                let scaling = (self.scaling * (1.0 + y / 30.0))
-                                    .clamp(Self::MIN_SCALING, Self::MAX_SCALING);
-
-                                let translation = if let Some(cursor_to_center) =
-                                    cursor.position_from(bounds.center())
-                                {
-                                    let factor = scaling - old_scaling;
-
-                                    Some(
-                                        self.translation
-                                            - Vector::new(
-                                                cursor_to_center.x * factor
-                                                    / (old_scaling * old_scaling),
-                                                cursor_to_center.y * factor
-                                                    / (old_scaling * old_scaling),
-                                            ),
-                                    )
-                                } else {
-                                    None
-                                };
-
+                                let scaling = self.scaling * (1.0 + y / 30.0);
                                 (
                                     event::Status::Captured,
-                                    Some(Message::Scaled(scaling, translation)),
+                                    Some(self.zoom(
+                                        scaling,
+                                        cursor.position().map(|p| p - bounds.center()),
+                                    )),
                                 )
                             } else {
                                 (event::Status::Captured, None)
@@ -631,7 +597,7 @@ mod grid {
 
         fn draw(
             &self,
-            _interaction: &Interaction,
+            _controls: &Controls,
             renderer: &Renderer,
             _theme: &Theme,
             bounds: Rectangle,
@@ -760,11 +726,11 @@ mod grid {
 
         fn mouse_interaction(
             &self,
-            interaction: &Interaction,
+            controls: &Controls,
             bounds: Rectangle,
             cursor: mouse::Cursor,
         ) -> mouse::Interaction {
-            match interaction {
+            match controls.interaction {
                 Interaction::Drawing => mouse::Interaction::Crosshair,
                 Interaction::Erasing => mouse::Interaction::Crosshair,
                 Interaction::Panning { .. } => mouse::Interaction::Grabbing,
@@ -980,6 +946,12 @@ mod grid {
         }
     }
 
+    #[derive(Default)]
+    pub struct Controls {
+        interaction: Interaction,
+        pinch: Pinch,
+    }
+
     pub enum Interaction {
         None,
         Drawing,
@@ -995,11 +967,16 @@ mod grid {
 }
 
 fn main() {
-    helenos_iced::run(
-        |create_send_msg| GameOfLife::new(create_send_msg),
+    if let Err(e) = helenos_iced::run(
+        |create_send_msg, settings| GameOfLife::new(create_send_msg, settings),
         WindowOptions {
             caption: Cow::Borrowed("Game of Life"),
             maximized: true,
+            theme: Theme::Dark,
+            ..Default::default()
         },
-    );
+    ) {
+        error!("{e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,81 @@
+//! Delivery of messages sent from other threads, and of the actions of tasks, to the event loop.
+//!
+//! Both go through one unbounded channel, which the event loop drains before drawing a frame,
+//! in the order they were sent. Sending never blocks and doesn't take any lock of the event
+//! loop, so a [`SendMsgFn`] can be called from any thread at any time, even by a thread that
+//! is being joined when the program exits. Whatever is sent after that is dropped.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::task::{Context, Poll};
+
+use iced_runtime::Action;
+use iced_runtime::futures::futures::Sink;
+use iced_runtime::futures::futures::channel::mpsc::SendError;
+
+use crate::SendMsgFn;
+
+pub(crate) type Receiver<M> = mpsc::Receiver<Action<M>>;
+
+pub(crate) struct Sender<M> {
+    sender: mpsc::Sender<Action<M>>,
+    // tells the event loop that there is something to drain
+    wake: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl<M> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            wake: self.wake.clone(),
+        }
+    }
+}
+
+impl<M: Send + 'static> Sender<M> {
+    pub(crate) fn send(&self, action: Action<M>) {
+        // fails only once the event loop is gone
+        if self.sender.send(action).is_ok() {
+            (self.wake)();
+        }
+    }
+
+    pub(crate) fn send_msg_fn(&self) -> SendMsgFn<M> {
+        let sender = self.clone();
+        Box::new(move |msg| sender.send(Action::Output(msg)))
+    }
+}
+
+/// Lets the runtime send the actions of tasks and subscriptions directly from their futures
+impl<M: Send + 'static> Sink<Action<M>> for Sender<M> {
+    // required by the runtime, sending never fails
+    type Error = SendError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, action: Action<M>) -> Result<(), Self::Error> {
+        self.send(action);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Creates the channel, `wake` is called after every sent message or action
+pub(crate) fn channel<M>(wake: impl Fn() + Send + Sync + 'static) -> (Sender<M>, Receiver<M>) {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Sender {
+        sender,
+        wake: Arc::new(wake),
+    };
+    (sender, receiver)
+}
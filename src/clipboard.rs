@@ -0,0 +1,74 @@
+//! Clipboard shared by widgets (e.g. copy & paste in `text_input`) and clipboard tasks.
+
+use std::cell::RefCell;
+
+use iced_widget::core::clipboard::Kind;
+
+use crate::error;
+
+pub use iced_runtime::clipboard::{read, read_primary, write, write_primary};
+
+/// Clipboard provided by the platform
+pub(crate) trait System {
+    /// Returns `None` if the clipboard is empty or doesn't contain text
+    fn read(&mut self, kind: Kind) -> Result<Option<String>, String>;
+    fn write(&mut self, kind: Kind, contents: &str) -> Result<(), String>;
+}
+
+/// Clipboard backed by the platform one when available. Contents are also kept in-process, which
+/// is used as a fallback when there is no platform clipboard or it fails.
+#[derive(Default)]
+pub(crate) struct Clipboard {
+    // `read` only gets a shared reference, but the platform clipboards need a mutable one
+    system: Option<RefCell<Box<dyn System>>>,
+    standard: Option<String>,
+    primary: Option<String>,
+}
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clipboard")
+            .field("system", &self.system.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clipboard {
+    pub(crate) fn new(system: Option<Box<dyn System>>) -> Self {
+        Self {
+            system: system.map(RefCell::new),
+            ..Default::default()
+        }
+    }
+
+    fn store(&mut self, kind: Kind) -> &mut Option<String> {
+        match kind {
+            Kind::Standard => &mut self.standard,
+            Kind::Primary => &mut self.primary,
+        }
+    }
+}
+
+impl iced_widget::core::Clipboard for Clipboard {
+    fn read(&self, kind: Kind) -> Option<String> {
+        if let Some(system) = &self.system {
+            match system.borrow_mut().read(kind) {
+                Ok(contents) => return contents,
+                Err(e) => error!("Error reading clipboard: {e}"),
+            }
+        }
+        match kind {
+            Kind::Standard => self.standard.clone(),
+            Kind::Primary => self.primary.clone(),
+        }
+    }
+
+    fn write(&mut self, kind: Kind, contents: String) {
+        if let Some(system) = &mut self.system {
+            if let Err(e) = system.get_mut().write(kind, &contents) {
+                error!("Error writing clipboard: {e}");
+            }
+        }
+        *self.store(kind) = Some(contents);
+    }
+}
@@ -0,0 +1,90 @@
+//! Overlay with performance statistics of the program, drawn over its widgets.
+//!
+//! Timings of update, view, layout, event processing, drawing and presenting are collected by
+//! [`iced_runtime::Debug`], this adds the frame rate and draws it all.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use iced_runtime::Debug;
+use iced_widget::Renderer;
+use iced_widget::core::text::Shaping;
+use iced_widget::core::{Color, Event, Font, Point, Rectangle, Size, keyboard};
+
+use crate::panel;
+
+const STYLE: panel::Style = panel::Style {
+    background: Color::from_rgba(0.0, 0.0, 0.0, 0.7),
+    border_color: Color::TRANSPARENT,
+    border_width: 0.0,
+    text_color: Color::WHITE,
+    font: Font::with_name("Noto Sans Mono"),
+    text_size: 12.0,
+    line_height: 16.0,
+    padding: 5.0,
+    shaping: Shaping::Basic,
+};
+
+/// Whether the event is the key toggling the overlay
+pub(crate) fn is_toggle_key(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            key: keyboard::Key::Named(keyboard::key::Named::F12),
+            ..
+        })
+    )
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DebugOverlay {
+    enabled: bool,
+    // times of the frames drawn during the last second
+    frames: VecDeque<Instant>,
+}
+
+impl DebugOverlay {
+    pub(crate) fn new(enabled: bool, debug: &mut Debug) -> Self {
+        let mut overlay = Self::default();
+        if enabled {
+            overlay.toggle(debug);
+        }
+        overlay
+    }
+
+    pub(crate) fn toggle(&mut self, debug: &mut Debug) {
+        self.enabled = !self.enabled;
+        self.frames.clear();
+        debug.toggle();
+    }
+
+    pub(crate) fn frame_drawn(&mut self, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+        while self
+            .frames
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > Duration::from_secs(1))
+        {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(now);
+    }
+
+    /// Draws the statistics over everything the program drew into the renderer
+    pub(crate) fn draw(&self, renderer: &mut Renderer, debug: &Debug, bounds: Size) {
+        if !self.enabled {
+            return;
+        }
+        let lines: Vec<String> = std::iter::once(format!("Frame rate: {} fps", self.frames.len()))
+            .chain(debug.overlay().into_iter().skip(1))
+            .collect();
+
+        let panel = Rectangle::new(
+            Point::ORIGIN,
+            Size::new(bounds.width, STYLE.height(lines.len()).min(bounds.height)),
+        );
+        panel::draw_text_panel(renderer, lines, panel, &STYLE);
+    }
+}
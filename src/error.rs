@@ -0,0 +1,47 @@
+//! Errors of running a program
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Connecting to the display server or creating the event loop failed
+    Display(String),
+    /// Creating the window failed
+    Window(String),
+    /// Creating the software surface of the window failed
+    Surface(softbuffer::SoftBufferError),
+    /// Presenting a frame failed. This doesn't stop the program, it is only reported to
+    /// [`crate::ProgramExt::render_error`].
+    Present(softbuffer::SoftBufferError),
+    /// Spawning a background thread of the runtime failed
+    Thread(std::io::Error),
+    /// The script of the headless backend or a recording to replay couldn't be read, parsed
+    /// or run
+    Script(String),
+    /// The file to record the input into couldn't be written
+    Record(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Display(e) => write!(f, "Failed to open display: {e}"),
+            Error::Window(e) => write!(f, "Failed to create window: {e}"),
+            Error::Surface(e) => write!(f, "Failed to create surface: {e}"),
+            Error::Present(e) => write!(f, "Failed to present frame: {e}"),
+            Error::Thread(e) => write!(f, "Failed to spawn thread: {e}"),
+            Error::Script(e) => write!(f, "Script failed: {e}"),
+            Error::Record(e) => write!(f, "Failed to write recording: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Surface(e) | Error::Present(e) => Some(e),
+            Error::Thread(e) | Error::Record(e) => Some(e),
+            Error::Display(_) | Error::Window(_) | Error::Script(_) => None,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! A small single-threaded executor for running [`Task`]s and subscriptions.
+//!
+//! All futures are polled on one background thread, so a future that blocks delays all
+//! the other ones until it finishes. Blocking work belongs to [`thread::spawn_blocking`].
+//!
+//! [`Task`]: iced_runtime::Task
+//! [`thread::spawn_blocking`]: crate::thread::spawn_blocking
+
+use std::thread::JoinHandle;
+
+use iced_runtime::futures::futures::channel::mpsc;
+use iced_runtime::futures::futures::executor::LocalPool;
+use iced_runtime::futures::futures::future::BoxFuture;
+use iced_runtime::futures::futures::task::LocalSpawnExt;
+use iced_runtime::futures::futures::{Future, StreamExt};
+
+use crate::error;
+
+#[derive(Debug)]
+pub struct Executor {
+    sender: mpsc::UnboundedSender<BoxFuture<'static, ()>>,
+    _handle: JoinHandle<()>,
+}
+
+impl iced_runtime::futures::Executor for Executor {
+    fn new() -> Result<Self, std::io::Error> {
+        let (sender, mut receiver) = mpsc::unbounded::<BoxFuture<'static, ()>>();
+        let handle = std::thread::Builder::new()
+            .name("iced-executor".to_owned())
+            .spawn(move || {
+                let mut pool = LocalPool::new();
+                let spawner = pool.spawner();
+                // runs until the executor is dropped, all unfinished futures are dropped then
+                pool.run_until(async move {
+                    while let Some(future) = receiver.next().await {
+                        if let Err(e) = spawner.spawn_local(future) {
+                            error!("Error spawning future: {e}");
+                        }
+                    }
+                });
+            })?;
+        Ok(Self {
+            sender,
+            _handle: handle,
+        })
+    }
+
+    fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        // only fails when the executor thread is gone, in which case there is nobody to run it
+        let _ = self.sender.unbounded_send(Box::pin(future));
+    }
+}
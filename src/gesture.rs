@@ -0,0 +1,70 @@
+//! Gestures made of several touch events, which iced doesn't recognize by itself.
+
+use iced_widget::core::Point;
+use iced_widget::core::touch::{self, Finger};
+
+// fingers closer than this many pixels can't tell the change of their distance reliably
+const MIN_SPAN: f32 = 1.0;
+
+/// Recognizes two fingers moving apart or together, usually mapped to zooming. Feed it all the
+/// touch events, e.g. in [`canvas::Program::update`] or from a subscription to the events.
+///
+/// [`canvas::Program::update`]: iced_widget::canvas::Program::update
+#[derive(Debug, Clone, Default)]
+pub struct Pinch {
+    // the first two fingers touching the window, the others are ignored
+    fingers: Vec<(Finger, Point)>,
+}
+
+/// Change of a pinch since the previous touch event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PinchGesture {
+    /// Ratio of the new distance of the fingers to the previous one, more than 1 when they
+    /// move apart
+    pub scale: f32,
+    /// The point in the middle between the fingers
+    pub center: Point,
+}
+
+impl Pinch {
+    /// Updates the fingers, returns the change when one of the two pinching fingers moved
+    pub fn update(&mut self, event: &touch::Event) -> Option<PinchGesture> {
+        match *event {
+            touch::Event::FingerPressed { id, position } => {
+                if self.fingers.len() < 2 {
+                    self.fingers.push((id, position));
+                }
+                None
+            }
+            touch::Event::FingerMoved { id, position } => {
+                let i = self.fingers.iter().position(|(finger, _)| *finger == id)?;
+                let before = self.span();
+                self.fingers[i].1 = position;
+                let ((before, _), (after, center)) = (before?, self.span()?);
+                Some(PinchGesture {
+                    scale: after / before,
+                    center,
+                })
+            }
+            touch::Event::FingerLifted { id, .. } | touch::Event::FingerLost { id, .. } => {
+                self.fingers.retain(|(finger, _)| *finger != id);
+                None
+            }
+        }
+    }
+
+    /// Whether two fingers are touching, so their movement is a pinch rather than a drag
+    pub fn is_pinching(&self) -> bool {
+        self.fingers.len() == 2
+    }
+
+    // distance of the two fingers and the point between them
+    fn span(&self) -> Option<(f32, Point)> {
+        let [(_, a), (_, b)] = self.fingers[..] else {
+            return None;
+        };
+        let distance = a.distance(b);
+        let center = Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+        (distance >= MIN_SPAN).then_some((distance, center))
+    }
+}
@@ -0,0 +1,147 @@
+//! Composed text input with an input method (IME).
+//!
+//! iced 0.13 has no events for input methods, so the text they commit is typed into the
+//! widgets as key presses, one per character, and the text being composed (preedit) is drawn
+//! in a box below the focused text input. The input method is only enabled while a text
+//! input is focused, and its candidate window is placed at it.
+//!
+//! Dead keys are composed by the platform, the composed character comes as the text of the
+//! next key press.
+
+use iced_runtime::user_interface::UserInterface;
+use iced_widget::Renderer;
+use iced_widget::core::text::Shaping;
+use iced_widget::core::widget::Id;
+use iced_widget::core::widget::operation::{Focusable, Operation, Scrollable, TextInput};
+use iced_widget::core::{self, Color, Event, Font, Point, Rectangle, Size, Vector, keyboard};
+
+use crate::panel;
+
+const STYLE: panel::Style = panel::Style {
+    background: Color::WHITE,
+    border_color: Color::BLACK,
+    border_width: 1.0,
+    text_color: Color::BLACK,
+    font: Font::with_name("Noto Sans"),
+    text_size: 14.0,
+    line_height: 20.0,
+    padding: 3.0,
+    shaping: Shaping::Advanced,
+};
+
+/// Key presses typing the text committed by an input method
+pub(crate) fn commit_events(text: &str) -> impl Iterator<Item = Event> + '_ {
+    text.chars().filter(|c| !c.is_control()).flat_map(|c| {
+        let key = keyboard::Key::Character(c.to_string().into());
+        [
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key: key.clone(),
+                modified_key: key.clone(),
+                physical_key: keyboard::key::Physical::Unidentified(
+                    keyboard::key::NativeCode::Unidentified,
+                ),
+                location: keyboard::Location::Standard,
+                // no modifiers, so that the text isn't taken for a shortcut like Ctrl+C
+                modifiers: keyboard::Modifiers::empty(),
+                text: Some(c.to_string().into()),
+            }),
+            Event::Keyboard(keyboard::Event::KeyReleased {
+                key,
+                location: keyboard::Location::Standard,
+                modifiers: keyboard::Modifiers::empty(),
+            }),
+        ]
+    })
+}
+
+/// Bounds of the focused text input, in logical pixels.
+///
+/// Text inputs don't tell their own bounds to operations, so these are the bounds of the
+/// innermost container, row or column around it.
+pub(crate) fn focused_text_input<M, T, R: core::Renderer>(
+    user_interface: &mut UserInterface<'_, M, T, R>,
+    renderer: &R,
+    size: Size,
+) -> Option<Rectangle> {
+    let mut finder = FocusFinder {
+        bounds: Rectangle::with_size(size),
+        offset: Vector::ZERO,
+        scroll_translation: None,
+        last_focused: None,
+        found: None,
+    };
+    user_interface.operate(renderer, &mut finder);
+    finder.found
+}
+
+struct FocusFinder {
+    // innermost container around the visited widgets
+    bounds: Rectangle,
+    // scroll translation of the visited widgets
+    offset: Vector,
+    // translation of the scrollable whose container is visited next
+    scroll_translation: Option<Vector>,
+    // bounds of the last focusable widget, if it was focused
+    last_focused: Option<Rectangle>,
+    found: Option<Rectangle>,
+}
+
+impl Operation for FocusFinder {
+    fn container(
+        &mut self,
+        _id: Option<&Id>,
+        bounds: Rectangle,
+        operate_on_children: &mut dyn FnMut(&mut dyn Operation),
+    ) {
+        let (parent_bounds, parent_offset) = (self.bounds, self.offset);
+        self.bounds = bounds - self.offset;
+        // the content of a scrollable is moved, not the scrollable itself
+        self.offset = self.offset + self.scroll_translation.take().unwrap_or(Vector::ZERO);
+        operate_on_children(self);
+        (self.bounds, self.offset) = (parent_bounds, parent_offset);
+    }
+
+    fn scrollable(
+        &mut self,
+        _state: &mut dyn Scrollable,
+        _id: Option<&Id>,
+        _bounds: Rectangle,
+        _content_bounds: Rectangle,
+        translation: Vector,
+    ) {
+        self.scroll_translation = Some(translation);
+    }
+
+    fn focusable(&mut self, state: &mut dyn Focusable, _id: Option<&Id>) {
+        self.last_focused = state.is_focused().then_some(self.bounds);
+    }
+
+    fn text_input(&mut self, _state: &mut dyn TextInput, _id: Option<&Id>) {
+        // text inputs report themselves as focusable right before
+        if let Some(bounds) = self.last_focused.take() {
+            self.found = Some(bounds);
+        }
+    }
+}
+
+/// Draws the text being composed in a box below the focused text input
+pub(crate) fn draw_preedit(
+    renderer: &mut Renderer,
+    preedit: &str,
+    area: Rectangle,
+    viewport: Size,
+) {
+    if preedit.is_empty() {
+        return;
+    }
+    let width = area.width.max(100.0).min(viewport.width);
+    let height = STYLE.height(1);
+    let mut position = Point::new(area.x, area.y + area.height);
+    // above the input when there is no room below it
+    if position.y + height > viewport.height {
+        position.y = area.y - height;
+    }
+    position.x = position.x.min(viewport.width - width).max(0.0);
+    let panel = Rectangle::new(position, Size::new(width, height));
+    panel::draw_text_panel(renderer, [preedit.to_owned()], panel, &STYLE);
+}
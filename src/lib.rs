@@ -3,133 +3,953 @@
 #![feature(float_minimum_maximum)]
 #![feature(debug_closure_helpers)]
 #![feature(thread_sleep_until)]
+#![feature(associated_type_defaults)]
 
 use std::borrow::Cow;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Instant;
 
-use iced_runtime::Program;
-use iced_tiny_skia::Settings;
-use iced_widget::core::mouse::Cursor;
+use iced_runtime::futures::{Executor as _, Runtime, subscription};
+use iced_runtime::{Action, Program, Task, task};
+use iced_widget::core::mouse::{Cursor, Interaction};
 use iced_widget::core::renderer::Style;
-use iced_widget::core::{Color, Pixels, Size, clipboard, font};
-use iced_widget::graphics::{Compositor, Viewport};
-use iced_widget::runtime::program::State;
+use iced_widget::core::{
+    Clipboard as _, Color, Event, Font, Pixels, Point, Rectangle, Size, event,
+};
+use iced_widget::graphics::Viewport;
 use iced_widget::{Renderer, Theme};
 
-#[cfg(not(target_os = "helenos"))]
 mod platform {
+    #[cfg(not(target_os = "helenos"))]
     mod winit;
+    #[cfg(not(target_os = "helenos"))]
     pub use winit::*;
-}
 
-#[cfg(target_os = "helenos")]
-mod platform {
+    #[cfg(target_os = "helenos")]
     mod helenos;
+    #[cfg(target_os = "helenos")]
     pub use helenos::*;
+    // doesn't need HelenOS, so that it is tested everywhere
+    #[cfg(any(target_os = "helenos", test))]
+    mod helenos_keys;
+
+    pub mod headless;
 }
 
+mod channel;
+pub mod clipboard;
+mod debug_overlay;
+mod error;
+mod executor;
+pub mod gesture;
+mod ime;
+pub mod log;
+mod panel;
+pub mod recording;
+mod scheduler;
+pub mod screenshot;
+pub mod settings;
+pub mod shortcut;
+mod state;
+mod surface;
+pub mod thread;
+pub mod time;
+pub mod window;
+pub mod worker;
+
+use debug_overlay::DebugOverlay;
+use state::State;
+
+pub use error::Error;
+pub use iced_runtime::futures::Subscription;
+pub use platform::headless;
+
 pub type Element<'a, M> = iced_widget::core::Element<'a, M, Theme, Renderer>;
 
 pub trait ProgramExt: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static {
-    fn stop(&self);
+    /// Settings remembered between runs, see [`settings`]. They are loaded before the program
+    /// is created and passed to `create_app` of [`run`], defaults are used if there are none
+    /// saved yet.
+    type Settings: serde::Serialize + serde::de::DeserializeOwned + Default = ();
+
+    /// Called once the window is open, before the first frame is drawn. The returned task runs
+    /// like the ones returned from `update`.
+    fn on_start(&mut self) -> Task<Self::Message> {
+        Task::none()
+    }
+
+    /// Called when the window gains (`true`) or loses (`false`) the keyboard focus. The program
+    /// also receives [`window::Event::Focused`] and [`window::Event::Unfocused`].
+    fn on_focus_change(&mut self, _focused: bool) -> Task<Self::Message> {
+        Task::none()
+    }
+
+    /// Called when the platform suspends the program, e.g. when it is moved to the background,
+    /// so that it can pause its periodic work until [`ProgramExt::on_resume`]. Only reported by
+    /// winit, on the platforms which suspend applications.
+    fn on_suspend(&mut self) {}
+
+    fn on_resume(&mut self) -> Task<Self::Message> {
+        Task::none()
+    }
+
+    /// Called when the program exits, after its settings are saved. The supervised
+    /// [`thread`]s and [`worker`]s are stopped right after, so the program doesn't have to
+    /// join them.
+    fn on_exit(&mut self) {}
+
+    /// Settings to save when the program stops in a window, just before [`ProgramExt::on_exit`].
+    /// `None` keeps the saved ones.
+    fn settings(&self) -> Option<Self::Settings> {
+        None
+    }
+
+    /// Subscriptions of the program, evaluated again after every update. New subscriptions are
+    /// started and the ones that disappeared are stopped.
+    ///
+    /// Besides [`time::every`], the subscriptions from [`iced_runtime::futures::event`] and
+    /// [`iced_runtime::futures::keyboard`] can be used to listen to window and keyboard events.
+    fn subscription(&self) -> Subscription<Self::Message> {
+        Subscription::none()
+    }
+
+    /// Keyboard shortcuts of the program, see [`shortcut`]. Evaluated again on every key press,
+    /// and in every frame while they are listed by the overlay, so they can depend on the state
+    /// of the program.
+    fn shortcuts(&self) -> Vec<shortcut::Binding<Self::Message>> {
+        Vec::new()
+    }
+
+    /// Theme of the program, evaluated before every frame, so it can be switched at runtime.
+    /// `None` uses [`WindowOptions::theme`].
+    fn theme(&self) -> Option<Theme> {
+        None
+    }
+
+    /// Colours of the window with the given theme. `None` uses [`Appearance::of`] the theme,
+    /// with the background replaced by [`WindowOptions::background`] if set.
+    fn style(&self, _theme: &Theme) -> Option<Appearance> {
+        None
+    }
+
+    /// Called when the user asks to close the window, e.g. with its close button. Returning
+    /// `false` keeps the window open, for example to show an "unsaved changes" prompt first.
+    /// The program can close the window later by returning [`iced_runtime::exit`] from `update`.
+    ///
+    /// The program also receives [`window::Event::CloseRequested`] as a regular event.
+    fn close_requested(&mut self) -> bool {
+        true
+    }
+
+    /// Called when a frame couldn't be presented, e.g. because the window surface was lost.
+    /// The program keeps running and the next frame is drawn whole. The returned message is
+    /// queued, so the program can react, e.g. by showing the error.
+    fn render_error(&self, error: &Error) -> Option<Self::Message> {
+        error!("{error}");
+        None
+    }
+}
+
+/// Colours used for the parts of the window not covered by widgets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Appearance {
+    pub background_color: Color,
+    /// Default colour of text, unless a widget sets its own
+    pub text_color: Color,
 }
 
-struct AppInner<T: Program + 'static> {
+impl Appearance {
+    /// Background and text colours from the palette of the theme
+    pub fn of(theme: &Theme) -> Self {
+        let palette = theme.palette();
+        Self {
+            background_color: palette.background,
+            text_color: palette.text,
+        }
+    }
+}
+
+/// Something that [`AppInner`] can draw its frames into
+trait Target {
+    /// Size of the drawable area in physical pixels
+    fn physical_size(&self) -> Size<u32>;
+    fn scale_factor(&self) -> f64;
+    fn set_cursor(&self, interaction: Interaction);
+    /// Enables the input method with its candidate window at the area in physical pixels, or
+    /// disables it
+    fn set_ime_area(&self, area: Option<Rectangle>);
+    fn change_window(&mut self, request: window::Request, scale_factor: f64);
+    fn present(
+        &mut self,
+        renderer: &mut Renderer,
+        viewport: &Viewport,
+        background: Color,
+    ) -> Result<(), Error>;
+}
+
+/// Target backed by an actual window of the current platform
+struct WindowTarget {
     w: Arc<platform::Window>,
-    surface: iced_tiny_skia::window::Surface,
-    compositor: iced_tiny_skia::window::Compositor,
+    surface: surface::Surface,
+}
+
+impl Debug for WindowTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WindowTarget")
+            .field("w", &self.w)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Target for WindowTarget {
+    fn physical_size(&self) -> Size<u32> {
+        let s = self.w.inner_size();
+        Size::new(s.width, s.height)
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.w.scale_factor()
+    }
+
+    fn set_cursor(&self, interaction: Interaction) {
+        platform::set_cursor(&self.w, interaction);
+    }
+
+    fn set_ime_area(&self, area: Option<Rectangle>) {
+        platform::set_ime_area(&self.w, area);
+    }
+
+    fn change_window(&mut self, request: window::Request, scale_factor: f64) {
+        platform::change_window(&self.w, request, scale_factor);
+    }
+
+    fn present(
+        &mut self,
+        renderer: &mut Renderer,
+        viewport: &Viewport,
+        background: Color,
+    ) -> Result<(), Error> {
+        self.surface
+            .present(renderer, viewport, background)
+            .map_err(Error::Present)
+    }
+}
+
+struct AppInner<T: Program + 'static, S = WindowTarget> {
+    target: S,
     renderer: Renderer,
 
     program: State<T>,
+    // used when the program doesn't provide its own theme or style
+    default_theme: Theme,
+    background: Option<Color>,
+    // background of the last frame, to draw it again for screenshots
+    frame_background: Color,
+    clipboard: clipboard::Clipboard,
     debug: iced_widget::runtime::Debug,
+    debug_overlay: DebugOverlay,
+    id: window::Id,
+    // copy of the events queued in the program, so that they can be broadcast to subscriptions
+    queued_events: Vec<Event>,
+    // frame requested by the widgets, e.g. for animations
+    requested_frame: Option<Instant>,
+    // focused text input given to the input method, in logical pixels
+    ime_area: Option<Rectangle>,
+    // text being composed in the input method
+    preedit: String,
+    // the overlay listing the keyboard shortcuts is shown
+    shortcut_help: bool,
+    // keyboard shortcuts whose conflicts were checked last, and the conflicts already logged
+    checked_shortcuts: Vec<(shortcut::Shortcut, Cow<'static, str>)>,
+    shortcut_conflicts: Vec<(shortcut::Shortcut, String)>,
+    scale_override: Option<f64>,
+    // size of the last frame, to notice when the target was resized or rescaled
+    logical_size: Size,
+    recorder: Option<recording::Recorder>,
+    replay: Option<recording::Replay<T::Message>>,
+    // messages from other threads and actions of tasks, see `drain_actions`
+    receiver: channel::Receiver<T::Message>,
+    // threads and workers of the program, entered while its code runs
+    supervisor: thread::Supervisor,
+    runtime: Runtime<executor::Executor, channel::Sender<T::Message>, Action<T::Message>>,
 }
 
-impl<T> Debug for AppInner<T>
+impl<T, S> Debug for AppInner<T, S>
 where
-    T: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static,
+    T: ProgramExt,
+    S: Debug,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AppInner")
-            .field("w", &self.w)
+            .field("target", &self.target)
             .field("program", self.program.program())
             .finish_non_exhaustive()
     }
 }
 
-impl<T> AppInner<T>
+/// Loads the bundled fonts and the ones from the options into the global font system, shared
+/// by all renderers, and creates a renderer with the default font and text size of the options.
+/// Each font is loaded once, later renderers with the same options only reuse it.
+fn create_renderer(options: &WindowOptions) -> Renderer {
+    static BUNDLED_FONTS: Once = Once::new();
+    static LOADED_FONTS: Mutex<Vec<FontSource>> = Mutex::new(Vec::new());
+
+    let mut font_system = iced_widget::graphics::text::font_system().write().unwrap();
+    BUNDLED_FONTS.call_once(|| {
+        font_system.load_font(Cow::Borrowed(include_bytes!(
+            "../fonts/NotoSans-Regular.ttf"
+        )));
+        font_system.load_font(Cow::Borrowed(include_bytes!(
+            "../fonts/NotoSansMono-Regular.ttf"
+        )));
+    });
+    let mut loaded = LOADED_FONTS.lock().unwrap();
+    for source in &options.fonts {
+        if loaded.contains(source) {
+            continue;
+        }
+        match source {
+            FontSource::Bytes(bytes) => font_system.load_font(bytes.clone()),
+            FontSource::File(path) => match std::fs::read(path) {
+                Ok(bytes) => font_system.load_font(Cow::Owned(bytes)),
+                Err(e) => error!("Error loading font {}: {e}", path.display()),
+            },
+        }
+        loaded.push(source.clone());
+    }
+    Renderer::new(options.default_font, options.text_size)
+}
+
+impl<T, S> AppInner<T, S>
 where
-    T: Debug + Program<Renderer = Renderer, Theme = Theme> + 'static,
+    T: ProgramExt,
+    S: Target,
 {
+    /// Saves the settings of the program and stops it
+    fn stop(&mut self) {
+        if let Some(settings) = self.program.program().settings() {
+            settings::save(&settings);
+        }
+        self.exit();
+    }
+
+    /// Tells the program it exits and stops its threads, without saving the settings
+    fn exit(&mut self) {
+        {
+            let _entered = self.supervisor.enter();
+            self.program.program_mut().on_exit();
+        }
+        self.supervisor
+            .stop_all(Instant::now() + thread::EXIT_TIMEOUT);
+    }
+
+    /// Runs a task returned by one of the lifecycle hooks of the program, and draws a frame,
+    /// since the hook may have changed the program
+    fn run_hook(&mut self, hook: impl FnOnce(&mut T) -> Task<T::Message>) {
+        let _entered = self.supervisor.enter();
+        let task = hook(self.program.program_mut());
+        self.run_task(task);
+        self.requested_frame = Some(Instant::now());
+    }
+
+    fn suspend(&mut self) {
+        let _entered = self.supervisor.enter();
+        self.program.program_mut().on_suspend();
+    }
+
+    fn resume(&mut self) {
+        self.run_hook(T::on_resume);
+    }
+
+    /// Queues an event coming from the platform
+    fn queue_event(&mut self, event: Event) {
+        if self.replay.is_some() && recording::is_input(&event) {
+            // the user must not disturb the replayed input
+            return;
+        }
+        self.record(|recorder| recorder.event(&event));
+        self.push_event(event);
+    }
+
+    fn push_event(&mut self, event: Event) {
+        if debug_overlay::is_toggle_key(&event) {
+            self.debug_overlay.toggle(&mut self.debug);
+        }
+        if screenshot::is_hotkey(&event) {
+            screenshot::save(&self.screenshot());
+        }
+        if shortcut::is_help_key(&event) {
+            self.shortcut_help = !self.shortcut_help;
+        }
+        if shortcut::is_key_press(&event) {
+            let shortcuts = self.program.program().shortcuts();
+            self.check_shortcuts(&shortcuts);
+            let text_input_focused = self.program.text_input_area().is_some();
+            if let Some(msg) = shortcut::find(shortcuts, &event, text_input_focused) {
+                // the key press is recorded instead of the message, and replayed as a shortcut
+                // again
+                self.program.queue_message(msg);
+                return;
+            }
+        }
+        if let Event::Window(window::Event::Focused | window::Event::Unfocused) = event {
+            let focused = matches!(event, Event::Window(window::Event::Focused));
+            self.run_hook(|program| program.on_focus_change(focused));
+        }
+        self.queued_events.push(event.clone());
+        self.program.queue_event(event);
+    }
+
+    /// Types the text committed by the input method into the focused text input
+    fn commit_text(&mut self, text: &str) {
+        self.preedit.clear();
+        for event in ime::commit_events(text) {
+            self.queue_event(event);
+        }
+    }
+
+    /// Shows the text being composed in the input method
+    fn set_preedit(&mut self, text: String) {
+        if text != self.preedit {
+            self.preedit = text;
+            self.requested_frame = Some(Instant::now());
+        }
+    }
+
+    fn queue_message(&mut self, msg: T::Message) {
+        self.record(|recorder| recorder.message(&msg));
+        self.program.queue_message(msg);
+    }
+
+    /// Writes into the recording, if enabled. It is stopped after the first error.
+    fn record(&mut self, write: impl FnOnce(&mut recording::Recorder) -> std::io::Result<()>) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = write(recorder) {
+                error!("Error writing recording, stopping it: {e}");
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Queues the steps of the replayed recording that are due
+    fn replay_due_steps(&mut self) {
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        let steps = replay.due_steps(Instant::now());
+        let finished = replay.is_finished();
+        for step in steps {
+            match step {
+                headless::Step::Event(Event::Window(window::Event::CloseRequested)) => {
+                    if self.close_requested() {
+                        self.run_task(iced_runtime::exit());
+                    }
+                }
+                headless::Step::Event(event) => {
+                    self.record(|recorder| recorder.event(&event));
+                    self.push_event(event);
+                }
+                headless::Step::Message(msg) => self.queue_message(msg),
+                headless::Step::Frame(path) => {
+                    if let Err(e) = self.screenshot().save(&path) {
+                        error!("Error saving frame {}: {e}", path.display());
+                    }
+                }
+                // the window keeps its size, and the frames are drawn as usual
+                headless::Step::Resize(..) | headless::Step::Wait(_) | headless::Step::Redraw => {}
+            }
+        }
+        if finished {
+            info!("Replay finished");
+            self.replay = None;
+        }
+    }
+
+    fn change_window(&mut self, request: window::Request) {
+        let scale_factor = self.scale_factor();
+        self.target.change_window(request, scale_factor);
+        // the new size is picked up in the next frame
+        self.requested_frame = Some(Instant::now());
+    }
+
+    /// Scale factor of the interface, either the one of the platform or the one overridden in
+    /// [`WindowOptions::scale`]
+    fn scale_factor(&self) -> f64 {
+        self.scale_override
+            .unwrap_or_else(|| self.target.scale_factor())
+    }
+
+    fn viewport(&self) -> Viewport {
+        Viewport::with_physical_size(self.target.physical_size(), self.scale_factor())
+    }
+
+    /// Converts a position reported by the platform in physical pixels to logical ones, used by
+    /// the widgets
+    fn to_logical(&self, physical: Point) -> Point {
+        let scale = self.scale_factor() as f32;
+        Point::new(physical.x / scale, physical.y / scale)
+    }
+
     fn update(&mut self, cursor: Cursor) {
-        let s = self.w.inner_size();
+        let _entered = self.supervisor.enter();
+        self.replay_due_steps();
+        let cursor = self
+            .replay
+            .as_ref()
+            .map_or(cursor, |replay| replay.cursor());
+
+        let viewport = self.viewport();
+        let logical_size = viewport.logical_size();
+        if logical_size != self.logical_size {
+            self.logical_size = logical_size;
+            let physical_size = self.target.physical_size();
+            self.record(|recorder| recorder.resize(physical_size));
+            self.push_event(Event::Window(window::Event::Resized(logical_size)));
+        }
+        let now = Instant::now();
+        self.push_event(Event::Window(window::Event::RedrawRequested(now)));
+
+        let program = self.program.program();
+        let theme = program
+            .theme()
+            .unwrap_or_else(|| self.default_theme.clone());
+        let appearance = program.style(&theme).unwrap_or_else(|| Appearance {
+            background_color: self
+                .background
+                .unwrap_or_else(|| Appearance::of(&theme).background_color),
+            ..Appearance::of(&theme)
+        });
 
-        self.program.update(
-            Size::new(s.width as f32, s.height as f32),
+        let (uncaptured, task, redraw_request) = self.program.update(
+            logical_size,
             cursor,
             &mut self.renderer,
-            &Theme::Light,
-            &Style::default(),
-            &mut clipboard::Null,
+            &theme,
+            &Style {
+                text_color: appearance.text_color,
+            },
+            &mut self.clipboard,
             &mut self.debug,
         );
+        self.requested_frame =
+            redraw_request.map(|request| scheduler::requested_frame(now, request));
+        self.broadcast_events(uncaptured);
+        if let Some(task) = task {
+            self.run_task(task);
+        }
+        self.runtime.track(subscription::into_recipes(
+            self.program.program().subscription().map(Action::Output),
+        ));
 
-        platform::set_cursor(&self.w, self.program.mouse_interaction());
-
-        self.compositor
-            .configure_surface(&mut self.surface, s.width as u32, s.height as u32);
-        self.compositor
-            .present::<String>(
-                &mut self.renderer,
-                &mut self.surface,
-                &Viewport::with_physical_size(Size::new(s.width, s.height), self.w.scale_factor()),
-                Color::WHITE,
-                &[],
-            )
-            .unwrap();
-    }
-
-    fn new(w: Arc<platform::Window>, app: T) -> Self {
-        let mut compositor = iced_tiny_skia::window::compositor::new(
-            Settings {
-                default_font: font::Font {
-                    family: font::Family::Name("Noto Sans"),
-                    ..Default::default()
-                },
-                default_text_size: Pixels(12.0),
-            },
-            w.clone(),
-        );
-        compositor.load_font(Cow::Borrowed(include_bytes!("../fonts/NotoSans-Regular.ttf")));
-        compositor.load_font(Cow::Borrowed(include_bytes!("../fonts/NotoSansMono-Regular.ttf")));
+        self.target.set_cursor(self.program.mouse_interaction());
+        self.update_ime(logical_size);
+        self.update_shortcuts(logical_size);
+        self.debug_overlay
+            .draw(&mut self.renderer, &self.debug, logical_size);
+        self.debug.render_started();
+        self.frame_background = appearance.background_color;
+        let span = span!("present");
+        let presented =
+            self.target
+                .present(&mut self.renderer, &viewport, appearance.background_color);
+        drop(span);
+        self.debug.render_finished();
+        if let Err(e) = presented {
+            if let Some(msg) = self.program.program().render_error(&e) {
+                self.program.queue_message(msg);
+            }
+        }
+        self.debug_overlay.frame_drawn(now);
+        self.record(|recorder| recorder.frame());
+    }
+
+    /// Draws the overlay listing the keyboard shortcuts, if it is shown
+    fn update_shortcuts(&mut self, logical_size: Size) {
+        if !self.shortcut_help {
+            return;
+        }
+        let shortcuts = self.program.program().shortcuts();
+        self.check_shortcuts(&shortcuts);
+        shortcut::draw_help(&mut self.renderer, &shortcuts, logical_size);
+    }
+
+    /// Logs new conflicts of the keyboard shortcuts, if they changed since the last check
+    fn check_shortcuts(&mut self, shortcuts: &[shortcut::Binding<T::Message>]) {
+        let unchanged = self.checked_shortcuts.len() == shortcuts.len()
+            && self
+                .checked_shortcuts
+                .iter()
+                .zip(shortcuts)
+                .all(|((s, d), binding)| *s == binding.shortcut && *d == binding.description);
+        if unchanged {
+            return;
+        }
+        self.checked_shortcuts = shortcuts
+            .iter()
+            .map(|binding| (binding.shortcut.clone(), binding.description.clone()))
+            .collect();
+        let conflicts = shortcut::conflicts(shortcuts);
+        for (shortcut, conflict) in &conflicts {
+            if !self
+                .shortcut_conflicts
+                .iter()
+                .any(|(s, c)| s == shortcut && c == conflict)
+            {
+                warn!("Conflicting keyboard shortcut {shortcut}: {conflict}");
+            }
+        }
+        self.shortcut_conflicts = conflicts;
+    }
+
+    /// Moves the input method to the focused text input, and draws the text being composed
+    fn update_ime(&mut self, logical_size: Size) {
+        let area = self.program.text_input_area();
+        if area != self.ime_area {
+            self.ime_area = area;
+            let scale = self.scale_factor() as f32;
+            self.target.set_ime_area(area.map(|area| area * scale));
+            if area.is_none() {
+                self.preedit.clear();
+            }
+        }
+        if let Some(area) = area {
+            ime::draw_preedit(&mut self.renderer, &self.preedit, area, logical_size);
+        }
+    }
+
+    /// Renders the last frame again, into an image
+    fn screenshot(&mut self) -> image::RgbaImage {
+        let viewport = self.viewport();
+        screenshot::render_frame(&mut self.renderer, &viewport, self.frame_background)
+    }
+
+    /// Handles a request of the user to close the window. Returns whether it should be closed.
+    fn close_requested(&mut self) -> bool {
+        self.queue_event(Event::Window(window::Event::CloseRequested));
+        self.program.program_mut().close_requested()
+    }
+
+    /// When the next frame should be drawn, unless more events come before. `None` means
+    /// there is nothing to do until then.
+    fn next_redraw(&self) -> Option<Instant> {
+        if !self.program.is_queue_empty() {
+            return Some(Instant::now());
+        }
+        let replayed = self.replay.as_ref().and_then(|replay| replay.next_due());
+        match (self.requested_frame, replayed) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn broadcast_events(&mut self, uncaptured: Vec<Event>) {
+        // uncaptured events are a subsequence of the queued ones, in the same order
+        let mut uncaptured = uncaptured.into_iter().peekable();
+        for event in self.queued_events.drain(..) {
+            let status = if uncaptured.next_if_eq(&event).is_some() {
+                event::Status::Ignored
+            } else {
+                event::Status::Captured
+            };
+            self.runtime.broadcast(subscription::Event::Interaction {
+                window: self.id,
+                event,
+                status,
+            });
+        }
+    }
+
+    fn run_task(&mut self, task: Task<T::Message>) {
+        if let Some(stream) = task::into_stream(task) {
+            self.runtime.run(stream);
+        }
+    }
+
+    /// Handles everything sent to the channel of the program. Breaks if the program requested
+    /// to exit.
+    fn drain_actions(&mut self) -> ControlFlow<()> {
+        while let Ok(action) = self.receiver.try_recv() {
+            self.handle_action(action)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Performs an action produced by a task. Breaks if the program requested to exit.
+    fn handle_action(&mut self, action: Action<T::Message>) -> ControlFlow<()> {
+        match action {
+            Action::Output(msg) => self.queue_message(msg),
+            Action::LoadFont { bytes, channel } => {
+                iced_widget::graphics::text::font_system()
+                    .write()
+                    .unwrap()
+                    .load_font(bytes);
+                let _ = channel.send(Ok(()));
+            }
+            Action::Widget(mut operation) => {
+                let requests = window::Requests::take_from(operation.as_mut());
+                if requests.is_empty() {
+                    let logical_size = self.viewport().logical_size();
+                    self.program.operate(
+                        &mut self.renderer,
+                        std::iter::once(operation),
+                        logical_size,
+                        &mut self.debug,
+                    );
+                }
+                for request in requests {
+                    self.change_window(request);
+                }
+            }
+            Action::Clipboard(iced_runtime::clipboard::Action::Read { target, channel }) => {
+                let _ = channel.send(self.clipboard.read(target));
+            }
+            Action::Clipboard(iced_runtime::clipboard::Action::Write { target, contents }) => {
+                self.clipboard.write(target, contents);
+            }
+            Action::Window(iced_runtime::window::Action::GetOldest(channel))
+            | Action::Window(iced_runtime::window::Action::GetLatest(channel)) => {
+                let _ = channel.send(Some(self.id));
+            }
+            Action::Window(iced_runtime::window::Action::Resize(_, size)) => {
+                self.change_window(window::Request::Resize(size));
+            }
+            Action::Window(iced_runtime::window::Action::Move(_, position)) => {
+                self.change_window(window::Request::Move(position));
+            }
+            Action::Window(iced_runtime::window::Action::ChangeMode(_, mode)) => {
+                self.change_window(window::Request::Mode(mode));
+            }
+            Action::Window(iced_runtime::window::Action::Maximize(_, maximized)) => {
+                self.change_window(window::Request::Maximize(maximized));
+            }
+            Action::Window(iced_runtime::window::Action::ToggleMaximize(_)) => {
+                self.change_window(window::Request::ToggleMaximize);
+            }
+            Action::Window(iced_runtime::window::Action::Minimize(_, minimized)) => {
+                self.change_window(window::Request::Minimize(minimized));
+            }
+            Action::Window(iced_runtime::window::Action::ToggleDecorations(_)) => {
+                self.change_window(window::Request::ToggleDecorations);
+            }
+            Action::Window(iced_runtime::window::Action::GetSize(_, channel)) => {
+                let _ = channel.send(self.viewport().logical_size());
+            }
+            Action::Window(iced_runtime::window::Action::Screenshot(_, channel)) => {
+                let image = self.screenshot();
+                let size = Size::new(image.width(), image.height());
+                let _ = channel.send(iced_runtime::window::Screenshot::new(
+                    image.into_raw(),
+                    size,
+                    self.scale_factor(),
+                ));
+            }
+            Action::Exit => return ControlFlow::Break(()),
+            // dropping the action also drops any channel in it, which cancels the waiting task
+            other => warn!("Unsupported action: {other:?}"),
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn with_target(
+        target: S,
+        mut renderer: Renderer,
+        clipboard: clipboard::Clipboard,
+        app: T,
+        options: &WindowOptions,
+        (sender, receiver): (channel::Sender<T::Message>, channel::Receiver<T::Message>),
+        supervisor: thread::Supervisor,
+    ) -> Result<Self, Error> {
+        let executor = executor::Executor::new().map_err(Error::Thread)?;
 
-        let mut renderer = compositor.create_renderer();
         let mut debug = iced_widget::runtime::Debug::new();
-        Self {
-            surface: compositor.create_surface(w.clone(), 300, 200),
-            compositor,
-            w,
+        let logical_size = Viewport::with_physical_size(
+            target.physical_size(),
+            options.scale.unwrap_or_else(|| target.scale_factor()),
+        )
+        .logical_size();
+        let mut recorder = match &options.record {
+            Some(path) => Some(recording::Recorder::new(path).map_err(Error::Record)?),
+            None => None,
+        };
+        if let Some(recorder) = recorder.as_mut() {
+            recorder
+                .resize(target.physical_size())
+                .map_err(Error::Record)?;
+        }
+        let replay = match &options.replay {
+            Some(path) => Some(recording::Replay::load(path)?),
+            None => None,
+        };
+        let mut inner = Self {
+            target,
             program: State::new(app, Size::new(300.0, 200.0), &mut renderer, &mut debug),
+            default_theme: options.theme.clone(),
+            background: options.background,
+            frame_background: Color::WHITE,
+            clipboard,
+            debug_overlay: DebugOverlay::new(options.debug_overlay, &mut debug),
             debug,
             renderer,
-        }
+            id: window::Id::unique(),
+            queued_events: Vec::new(),
+            requested_frame: None,
+            ime_area: None,
+            preedit: String::new(),
+            shortcut_help: false,
+            checked_shortcuts: Vec::new(),
+            shortcut_conflicts: Vec::new(),
+            scale_override: options.scale,
+            logical_size,
+            recorder,
+            replay,
+            receiver,
+            supervisor,
+            runtime: Runtime::new(executor, sender),
+        };
+        // reported right away, not only once a key is pressed
+        let shortcuts = inner.program.program().shortcuts();
+        inner.check_shortcuts(&shortcuts);
+        inner.run_hook(T::on_start);
+        Ok(inner)
     }
 }
 
+impl<T: ProgramExt> AppInner<T> {
+    fn new(
+        w: Arc<platform::Window>,
+        app: T,
+        options: &WindowOptions,
+        channel: (channel::Sender<T::Message>, channel::Receiver<T::Message>),
+        supervisor: thread::Supervisor,
+    ) -> Result<Self, Error> {
+        Self::with_target(
+            WindowTarget {
+                surface: surface::Surface::new(w.clone()).map_err(Error::Surface)?,
+                w,
+            },
+            create_renderer(options),
+            clipboard::Clipboard::new(platform::system_clipboard()),
+            app,
+            options,
+            channel,
+            supervisor,
+        )
+    }
+}
+
+/// Sends a message to the program from another thread. It never blocks, the messages are
+/// handled by the event loop before the next frame, in the order they were sent. Messages sent
+/// after the program exited are dropped.
 pub type SendMsgFn<M> = Box<dyn Fn(M) + Send + 'static>;
 
+/// Font loaded at startup, see [`WindowOptions::fonts`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontSource {
+    /// Font embedded in the binary, e.g. with `include_bytes!`
+    Bytes(Cow<'static, [u8]>),
+    /// Font file read from disk
+    File(PathBuf),
+}
+
 pub struct WindowOptions {
     pub caption: Cow<'static, str>,
+    /// Initial size of the drawable area in logical pixels. `None` lets the platform decide.
+    pub size: Option<Size>,
+    /// Initial position of the top left corner of the window on the screen. `None` lets the
+    /// platform decide. Ignored on HelenOS, whose display server always places the windows.
+    pub position: Option<Point>,
+    /// The drawable area can't be shrunk below this size. On HelenOS it is 100x100 if not set.
+    pub min_size: Option<Size>,
+    /// The drawable area can't be enlarged beyond this size. Ignored on HelenOS.
+    pub max_size: Option<Size>,
+    pub resizable: bool,
+    /// Show the title bar and borders of the window
+    pub decorations: bool,
+    pub fullscreen: bool,
     pub maximized: bool,
+    /// Theme used unless the program provides one with [`ProgramExt::theme`]
+    pub theme: Theme,
+    /// Background colour of the window, instead of the one from the theme palette
+    pub background: Option<Color>,
+    /// Scale factor of the interface, instead of the one reported by the platform, e.g. `2.0`
+    /// to make everything twice as large on big monitors
+    pub scale: Option<f64>,
+    /// Font of text widgets which don't set their own. The bundled fonts are "Noto Sans"
+    /// and "Noto Sans Mono".
+    pub default_font: Font,
+    pub text_size: Pixels,
+    /// Fonts loaded in addition to the bundled ones, available to text widgets by their family
+    /// names.
+    ///
+    /// They are also used for characters missing in the font of a text (e.g. CJK or symbols),
+    /// but there is no way to order them: the text shaper first tries its own list of common
+    /// fonts for the script of the character, and then all loaded fonts in an unspecified order.
+    pub fonts: Vec<FontSource>,
+    /// Show the overlay with frame rate and timings of update, view, layout and presenting
+    /// from the start. It can also be toggled with F12.
+    pub debug_overlay: bool,
+    /// Record all input events into this file, see [`recording`]. Can also be set with the
+    /// [`recording::RECORD_ENV`] environment variable.
+    pub record: Option<PathBuf>,
+    /// Replay a recording in the window, ignoring the real input until it ends. Can also be set
+    /// with the [`recording::REPLAY_ENV`] environment variable.
+    pub replay: Option<PathBuf>,
 }
 
 impl Default for WindowOptions {
     fn default() -> Self {
         Self {
             caption: Cow::Borrowed("Iced App"),
+            size: None,
+            position: None,
+            min_size: None,
+            max_size: None,
+            resizable: true,
+            decorations: true,
+            fullscreen: false,
             maximized: false,
+            theme: Theme::Light,
+            background: None,
+            scale: None,
+            default_font: Font::with_name("Noto Sans"),
+            text_size: Pixels(12.0),
+            fonts: Vec::new(),
+            debug_overlay: false,
+            record: None,
+            replay: None,
         }
     }
 }
 
-pub use platform::run;
+/// Runs the program in a window until it exits. Fails if the window can't be opened; errors
+/// while the program is running are reported to [`ProgramExt::render_error`] instead.
+///
+/// `create_app` gets the saved [`ProgramExt::Settings`]. Headless scripts always get the
+/// default ones, so that they behave the same on every machine.
+pub fn run<T: ProgramExt>(
+    create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static), T::Settings) -> T
+    + 'static,
+    mut options: WindowOptions,
+) -> Result<(), Error> {
+    if let Some(path) = std::env::var_os(recording::RECORD_ENV) {
+        options.record = Some(path.into());
+    }
+    if let Some(path) = std::env::var_os(recording::REPLAY_ENV) {
+        options.replay = Some(path.into());
+    }
+    if let Some(script) = std::env::var_os(headless::SCRIPT_ENV) {
+        return headless::run_script_file(
+            |create_send_msg| create_app(create_send_msg, T::Settings::default()),
+            &options,
+            script.as_ref(),
+        );
+    }
+    let settings = settings::load();
+    platform::run(
+        move |create_send_msg| create_app(create_send_msg, settings),
+        options,
+    )
+}
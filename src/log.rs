@@ -0,0 +1,308 @@
+//! Logging of the library and the programs, to stderr and optionally into a file.
+//!
+//! Messages are logged with the [`error!`], [`warn!`], [`info!`], [`debug!`] and [`trace!`]
+//! macros, which take the same arguments as `format!`. Which of them are shown is set by the
+//! [`FILTER_ENV`] environment variable, a comma separated list of the default level and
+//! `module=level` pairs, e.g. `warn,helenos_iced::worker=debug,life=trace`. The most specific
+//! module wins, `off` hides everything. Without it, info and more severe messages are shown.
+//!
+//! [`span!`] measures how long a part of the program took. The library measures `update`,
+//! `view` and `present` of every frame: at trace level all of them are logged, at debug level
+//! only those longer than [`SLOW_SPAN`], so that slow frames can be attributed.
+//!
+//! [`error!`]: crate::error
+//! [`warn!`]: crate::warn
+//! [`info!`]: crate::info
+//! [`debug!`]: crate::debug
+//! [`trace!`]: crate::trace
+//! [`span!`]: crate::span
+
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Environment variable with the filter of the logged messages
+pub const FILTER_ENV: &str = "HELENOS_ICED_LOG";
+/// Environment variable with a path of a file to append the logged messages to, in addition to
+/// stderr
+pub const FILE_ENV: &str = "HELENOS_ICED_LOG_FILE";
+
+/// Spans taking longer than this, one frame at 60 Hz, are logged at debug level
+pub const SLOW_SPAN: Duration = Duration::from_millis(16);
+
+/// Severity of a message, from the most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        })
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("unknown log level {s:?}")),
+        }
+    }
+}
+
+struct Logger {
+    start: Instant,
+    // most severe level shown, `None` shows nothing
+    default: Option<Level>,
+    // the same for modules and their submodules, the longest module first
+    modules: Vec<(String, Option<Level>)>,
+    file: Option<Mutex<LineWriter<File>>>,
+}
+
+impl Logger {
+    fn from_env() -> Self {
+        let mut logger = Logger {
+            start: Instant::now(),
+            default: Some(Level::Info),
+            modules: Vec::new(),
+            file: None,
+        };
+        if let Some(filter) = std::env::var_os(FILTER_ENV) {
+            logger.parse_filter(&filter.to_string_lossy());
+        }
+        if let Some(path) = std::env::var_os(FILE_ENV) {
+            match File::options().create(true).append(true).open(&path) {
+                Ok(file) => logger.file = Some(Mutex::new(LineWriter::new(file))),
+                // the logger isn't ready yet to report its own errors
+                Err(e) => eprintln!("Error opening log file {}: {e}", path.display()),
+            }
+        }
+        logger
+    }
+
+    fn parse_filter(&mut self, filter: &str) {
+        for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (module, level) = match directive.split_once('=') {
+                Some((module, level)) => (Some(module.trim()), level.trim()),
+                None => (None, directive),
+            };
+            let level = match level {
+                "off" => None,
+                level => match level.parse() {
+                    Ok(level) => Some(level),
+                    Err(e) => {
+                        eprintln!("Ignoring {directive:?} in {FILTER_ENV}: {e}");
+                        continue;
+                    }
+                },
+            };
+            match module {
+                Some(module) => self.modules.push((module.to_owned(), level)),
+                None => self.default = level,
+            }
+        }
+        self.modules
+            .sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+    }
+
+    fn enabled(&self, level: Level, module: &str) -> bool {
+        let max_level = self
+            .modules
+            .iter()
+            .find(|(prefix, _)| {
+                module
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level);
+        max_level.is_some_and(|max_level| level <= max_level)
+    }
+}
+
+fn logger() -> &'static Logger {
+    static LOGGER: OnceLock<Logger> = OnceLock::new();
+    LOGGER.get_or_init(Logger::from_env)
+}
+
+/// Whether messages of the level from the module are shown
+pub fn enabled(level: Level, module: &str) -> bool {
+    logger().enabled(level, module)
+}
+
+/// Logs a message from the module, usually called through the macros
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    let logger = logger();
+    if !logger.enabled(level, module) {
+        return;
+    }
+    let time = logger.start.elapsed().as_secs_f64();
+    let line = format!("{time:10.3} {level:<5} {module}: {args}\n");
+    // nowhere to report failures of writing the log
+    let _ = std::io::stderr().write_all(line.as_bytes());
+    if let Some(file) = &logger.file {
+        let _ = file.lock().unwrap().write_all(line.as_bytes());
+    }
+}
+
+/// Measures the time until it is dropped, see [`span!`](crate::span)
+#[derive(Debug)]
+#[must_use = "the span ends when it is dropped"]
+pub struct Span {
+    module: &'static str,
+    name: &'static str,
+    // `None` when the span wouldn't be logged anyway
+    start: Option<Instant>,
+}
+
+impl Span {
+    pub fn enter(module: &'static str, name: &'static str) -> Self {
+        Span {
+            module,
+            name,
+            start: enabled(Level::Debug, module).then(Instant::now),
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if elapsed >= SLOW_SPAN {
+            log(
+                Level::Debug,
+                self.module,
+                format_args!("slow {}: {elapsed:?}", self.name),
+            );
+        } else {
+            log(
+                Level::Trace,
+                self.module,
+                format_args!("{}: {elapsed:?}", self.name),
+            );
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Error, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Warn, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Info, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Debug, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Trace, module_path!(), format_args!($($arg)+))
+    };
+}
+
+/// Starts a [`Span`] named by the string literal, which ends at the end of the scope:
+/// `let _span = span!("decode");`
+#[macro_export]
+macro_rules! span {
+    ($name:literal) => {
+        $crate::log::Span::enter(module_path!(), $name)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_filter(filter: &str) -> Logger {
+        let mut logger = Logger {
+            start: Instant::now(),
+            default: Some(Level::Info),
+            modules: Vec::new(),
+            file: None,
+        };
+        logger.parse_filter(filter);
+        logger
+    }
+
+    #[test]
+    fn default_level() {
+        let logger = with_filter("");
+        assert!(logger.enabled(Level::Info, "life"));
+        assert!(!logger.enabled(Level::Debug, "life"));
+
+        let logger = with_filter("warn");
+        assert!(logger.enabled(Level::Error, "life"));
+        assert!(!logger.enabled(Level::Info, "life"));
+    }
+
+    #[test]
+    fn longest_module_prefix_wins() {
+        // in any order, and with spaces
+        let logger = with_filter("helenos_iced::worker=trace, error ,helenos_iced=warn");
+        assert!(logger.enabled(Level::Trace, "helenos_iced::worker"));
+        assert!(logger.enabled(Level::Trace, "helenos_iced::worker::tests"));
+        assert!(logger.enabled(Level::Warn, "helenos_iced::thread"));
+        assert!(!logger.enabled(Level::Info, "helenos_iced"));
+        assert!(!logger.enabled(Level::Warn, "life"));
+        // only whole path segments match
+        assert!(!logger.enabled(Level::Warn, "helenos_iced_extra"));
+        assert!(!logger.enabled(Level::Debug, "helenos_iced::workers"));
+    }
+
+    #[test]
+    fn off() {
+        let logger = with_filter("off,life=debug,helenos_iced::log=off");
+        assert!(!logger.enabled(Level::Error, "imageviewer"));
+        assert!(logger.enabled(Level::Debug, "life::grid"));
+        assert!(!logger.enabled(Level::Error, "helenos_iced::log"));
+    }
+
+    #[test]
+    fn invalid_directives_are_ignored() {
+        let logger = with_filter("loud,life=quiet,helenos_iced=DEBUG");
+        assert!(logger.enabled(Level::Info, "life"));
+        assert!(!logger.enabled(Level::Debug, "life"));
+        assert!(logger.enabled(Level::Debug, "helenos_iced"));
+    }
+}
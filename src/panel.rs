@@ -0,0 +1,72 @@
+//! Panels with lines of text, which the library draws over the widgets of the program: the
+//! debug overlay, the text being composed in an input method and the list of shortcuts.
+
+use iced_widget::Renderer;
+use iced_widget::core::renderer::{self, Renderer as _};
+use iced_widget::core::text::{self, LineHeight, Renderer as _, Shaping, Wrapping};
+use iced_widget::core::{Border, Color, Font, Pixels, Point, Rectangle, Size, alignment};
+
+/// How a panel looks
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Style {
+    pub(crate) background: Color,
+    pub(crate) border_color: Color,
+    pub(crate) border_width: f32,
+    pub(crate) text_color: Color,
+    pub(crate) font: Font,
+    pub(crate) text_size: f32,
+    pub(crate) line_height: f32,
+    pub(crate) padding: f32,
+    pub(crate) shaping: Shaping,
+}
+
+impl Style {
+    /// Height of a panel fitting the number of lines
+    pub(crate) fn height(&self, lines: usize) -> f32 {
+        lines as f32 * self.line_height + 2.0 * self.padding
+    }
+}
+
+/// Draws the panel with the lines of text, which are cut at its edges instead of wrapping
+pub(crate) fn draw_text_panel(
+    renderer: &mut Renderer,
+    lines: impl IntoIterator<Item = String>,
+    panel: Rectangle,
+    style: &Style,
+) {
+    renderer.with_layer(panel, |renderer| {
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds: panel,
+                border: Border {
+                    color: style.border_color,
+                    width: style.border_width,
+                    ..Border::default()
+                },
+                ..Default::default()
+            },
+            style.background,
+        );
+        for (i, line) in lines.into_iter().enumerate() {
+            renderer.fill_text(
+                text::Text {
+                    content: line,
+                    bounds: Size::new(panel.width - 2.0 * style.padding, style.line_height),
+                    size: Pixels(style.text_size),
+                    line_height: LineHeight::Absolute(Pixels(style.line_height)),
+                    font: style.font,
+                    horizontal_alignment: alignment::Horizontal::Left,
+                    vertical_alignment: alignment::Vertical::Top,
+                    shaping: style.shaping,
+                    wrapping: Wrapping::None,
+                },
+                Point::new(
+                    panel.x + style.padding,
+                    panel.y + style.padding + i as f32 * style.line_height,
+                ),
+                style.text_color,
+                panel,
+            );
+        }
+    });
+}
@@ -0,0 +1,913 @@
+//! Offscreen backend, which renders the program into a memory buffer instead of a window.
+//!
+//! There is no windowing system involved, so this can be used to exercise apps on machines
+//! without a display (e.g. in CI). Input is provided as a list of [`Step`]s, either built in
+//! code or parsed from a simple text script (see [`parse_script`]), and frames can be dumped
+//! as PNG files.
+//!
+//! [`crate::run`] switches to this backend when the [`SCRIPT_ENV`] environment variable
+//! is set to a path of a script file.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use iced_widget::Renderer;
+use iced_widget::core::keyboard::key::Named;
+use iced_widget::core::mouse::{self, Cursor, Interaction};
+use iced_widget::core::{Color, Event, Point, Rectangle, Size, keyboard, touch};
+use iced_widget::graphics::Viewport;
+use image::RgbaImage;
+
+use crate::clipboard::Clipboard;
+use crate::thread::Supervisor;
+use crate::{
+    AppInner, Error, ProgramExt, SendMsgFn, Target, WindowOptions, channel, create_renderer, window,
+};
+
+/// Environment variable with a path to a script, which makes [`crate::run`] use this backend
+pub const SCRIPT_ENV: &str = "HELENOS_ICED_HEADLESS";
+
+const DEFAULT_SIZE: Size<u32> = Size {
+    width: 800,
+    height: 600,
+};
+
+/// Offscreen render target backed by a tiny-skia pixmap
+pub(crate) struct Offscreen {
+    pixmap: tiny_skia::Pixmap,
+    clip_mask: tiny_skia::Mask,
+}
+
+impl std::fmt::Debug for Offscreen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Offscreen")
+            .field("pixmap", &self.pixmap)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Offscreen {
+    /// A pixmap of the size, at least one pixel in each direction
+    pub(crate) fn new(size: Size<u32>) -> Self {
+        let (width, height) = (size.width.max(1), size.height.max(1));
+        Self {
+            pixmap: tiny_skia::Pixmap::new(width, height).expect("Non-zero size"),
+            clip_mask: tiny_skia::Mask::new(width, height).expect("Non-zero size"),
+        }
+    }
+
+    pub(crate) fn to_image(&self) -> RgbaImage {
+        // the renderer draws with red and blue channels swapped, so that the result can be
+        // directly copied into a softbuffer surface
+        let data = self
+            .pixmap
+            .data()
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0], p[3]])
+            .collect();
+        RgbaImage::from_raw(self.pixmap.width(), self.pixmap.height(), data).unwrap()
+    }
+}
+
+impl Target for Offscreen {
+    fn physical_size(&self) -> Size<u32> {
+        Size::new(self.pixmap.width(), self.pixmap.height())
+    }
+
+    fn scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    fn set_cursor(&self, _interaction: Interaction) {}
+
+    fn set_ime_area(&self, _area: Option<Rectangle>) {}
+
+    fn change_window(&mut self, request: window::Request, scale_factor: f64) {
+        // only the size means something without a window
+        if let window::Request::Resize(size) = request {
+            let size = window::to_physical(size, scale_factor);
+            *self = Offscreen::new(size);
+        }
+    }
+
+    fn present(
+        &mut self,
+        renderer: &mut Renderer,
+        viewport: &Viewport,
+        background: Color,
+    ) -> Result<(), Error> {
+        renderer.draw::<String>(
+            &mut self.pixmap.as_mut(),
+            &mut self.clip_mask,
+            viewport,
+            &[Rectangle::with_size(viewport.logical_size())],
+            background,
+            &[],
+        );
+        Ok(())
+    }
+}
+
+/// One step of a headless run
+#[derive(Debug, Clone)]
+pub enum Step<M> {
+    /// Queue an input event
+    Event(Event),
+    /// Queue a message, as if it was sent by the program
+    Message(M),
+    /// Resize the offscreen buffer
+    Resize(u32, u32),
+    /// Wait for the given time, queueing all messages sent from other threads in the meantime
+    Wait(Duration),
+    /// Process everything queued so far and render a frame, without saving it
+    Redraw,
+    /// Process everything queued so far and save the resulting frame as PNG
+    Frame(PathBuf),
+}
+
+/// A program running without a window
+pub struct Headless<T: ProgramExt> {
+    inner: AppInner<T, Offscreen>,
+    cursor: Cursor,
+    // the program exited, like it would close its window
+    exited: bool,
+}
+
+impl<T: ProgramExt> std::fmt::Debug for Headless<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Headless")
+            .field("inner", &self.inner)
+            .field("cursor", &self.cursor)
+            .field("exited", &self.exited)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: ProgramExt> Headless<T> {
+    pub fn new(
+        create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static)) -> T,
+        options: &WindowOptions,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, Error> {
+        // the channel is drained while waiting for the messages, so it needs no wakeups
+        let (sender, receiver) = channel::channel(|| {});
+        let supervisor = Supervisor::default();
+        let app = {
+            let _entered = supervisor.enter();
+            create_app(&{
+                let sender = sender.clone();
+                move || sender.send_msg_fn()
+            })
+        };
+
+        Ok(Self {
+            inner: AppInner::with_target(
+                Offscreen::new(Size::new(width, height)),
+                create_renderer(options),
+                Clipboard::default(),
+                app,
+                options,
+                (sender, receiver),
+                supervisor,
+            )?,
+            cursor: Cursor::Unavailable,
+            exited: false,
+        })
+    }
+
+    pub fn program(&self) -> &T {
+        self.inner.program.program()
+    }
+
+    pub fn queue_event(&mut self, event: Event) {
+        match event {
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                self.cursor = Cursor::Available(position);
+            }
+            Event::Mouse(mouse::Event::CursorLeft) => {
+                self.cursor = Cursor::Unavailable;
+            }
+            Event::Touch(
+                touch::Event::FingerPressed { position, .. }
+                | touch::Event::FingerMoved { position, .. },
+            ) => {
+                self.cursor = Cursor::Available(position);
+            }
+            _ => {}
+        }
+        self.inner.queue_event(event);
+    }
+
+    pub fn queue_message(&mut self, msg: T::Message) {
+        self.inner.queue_message(msg);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.inner.target = Offscreen::new(Size::new(width, height));
+    }
+
+    /// Waits up to `timeout` for messages sent from other threads and tasks, and queues them.
+    /// Returns the number of received messages and other actions.
+    pub fn wait(&mut self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let mut received = 0;
+        while let Ok(action) = self
+            .inner
+            .receiver
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if self.inner.handle_action(action).is_break() {
+                self.exited = true;
+            }
+            received += 1;
+        }
+        received
+    }
+
+    /// Processes all queued events and messages, and renders a new frame
+    pub fn frame(&mut self) -> RgbaImage {
+        if self.inner.drain_actions().is_break() {
+            self.exited = true;
+        }
+        self.inner.update(self.cursor);
+        self.inner.target.to_image()
+    }
+
+    pub fn save_frame(&mut self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        self.frame().save(path)
+    }
+
+    /// Whether the program exited, either because it requested it or because it agreed to close
+    /// the window. It keeps working, but scripts end there.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Runs the steps until they end or the program exits
+    pub fn run_steps(
+        &mut self,
+        steps: impl IntoIterator<Item = Step<T::Message>>,
+    ) -> image::ImageResult<()> {
+        for step in steps {
+            if self.exited {
+                break;
+            }
+            match step {
+                Step::Event(Event::Window(window::Event::CloseRequested)) => {
+                    // handled like in a window, where the program may refuse to close
+                    if self.inner.close_requested() {
+                        self.exited = true;
+                    }
+                }
+                Step::Event(event) => self.queue_event(event),
+                Step::Message(msg) => self.queue_message(msg),
+                Step::Resize(width, height) => self.resize(width, height),
+                Step::Wait(timeout) => {
+                    self.wait(timeout);
+                }
+                Step::Redraw => {
+                    self.frame();
+                }
+                Step::Frame(path) => self.save_frame(path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: ProgramExt> Drop for Headless<T> {
+    fn drop(&mut self) {
+        // unlike in a window, the settings are not saved, so that scripts don't change them
+        self.inner.exit();
+    }
+}
+
+const NAMED_KEYS: &[(&str, Named)] = &[
+    ("Enter", Named::Enter),
+    ("Tab", Named::Tab),
+    ("Space", Named::Space),
+    ("Backspace", Named::Backspace),
+    ("Delete", Named::Delete),
+    ("Insert", Named::Insert),
+    ("Escape", Named::Escape),
+    ("ArrowUp", Named::ArrowUp),
+    ("ArrowDown", Named::ArrowDown),
+    ("ArrowLeft", Named::ArrowLeft),
+    ("ArrowRight", Named::ArrowRight),
+    ("Home", Named::Home),
+    ("End", Named::End),
+    ("PageUp", Named::PageUp),
+    ("PageDown", Named::PageDown),
+    ("Shift", Named::Shift),
+    ("Control", Named::Control),
+    ("Alt", Named::Alt),
+    ("Super", Named::Super),
+    ("Meta", Named::Meta),
+    ("CapsLock", Named::CapsLock),
+    ("NumLock", Named::NumLock),
+    ("ContextMenu", Named::ContextMenu),
+    ("PrintScreen", Named::PrintScreen),
+    ("F1", Named::F1),
+    ("F2", Named::F2),
+    ("F3", Named::F3),
+    ("F4", Named::F4),
+    ("F5", Named::F5),
+    ("F6", Named::F6),
+    ("F7", Named::F7),
+    ("F8", Named::F8),
+    ("F9", Named::F9),
+    ("F10", Named::F10),
+    ("F11", Named::F11),
+    ("F12", Named::F12),
+];
+
+const MODIFIERS: &[(&str, keyboard::Modifiers)] = &[
+    ("shift", keyboard::Modifiers::SHIFT),
+    ("ctrl", keyboard::Modifiers::CTRL),
+    ("alt", keyboard::Modifiers::ALT),
+    ("logo", keyboard::Modifiers::LOGO),
+];
+
+fn parse_button(s: &str) -> Result<mouse::Button, String> {
+    Ok(match s {
+        "left" => mouse::Button::Left,
+        "right" => mouse::Button::Right,
+        "middle" => mouse::Button::Middle,
+        "back" => mouse::Button::Back,
+        "forward" => mouse::Button::Forward,
+        other => mouse::Button::Other(
+            other
+                .parse()
+                .map_err(|_| format!("unknown mouse button: {other}"))?,
+        ),
+    })
+}
+
+fn format_button(button: mouse::Button) -> String {
+    match button {
+        mouse::Button::Left => "left".to_owned(),
+        mouse::Button::Right => "right".to_owned(),
+        mouse::Button::Middle => "middle".to_owned(),
+        mouse::Button::Back => "back".to_owned(),
+        mouse::Button::Forward => "forward".to_owned(),
+        mouse::Button::Other(n) => n.to_string(),
+    }
+}
+
+/// Named keys are written by name, e.g. `Enter`, characters as quoted strings, e.g. `"a"`
+fn parse_key(s: &str) -> Result<keyboard::Key, String> {
+    if s.starts_with('"') {
+        return Ok(keyboard::Key::Character(parse_quoted(s)?.into()));
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map(|(_, named)| keyboard::Key::Named(*named))
+        .ok_or_else(|| format!("unknown key: {s}"))
+}
+
+fn format_key(key: &keyboard::Key) -> Option<String> {
+    match key {
+        keyboard::Key::Named(named) => NAMED_KEYS
+            .iter()
+            .find(|(_, n)| n == named)
+            .map(|(name, _)| (*name).to_owned()),
+        keyboard::Key::Character(c) => Some(format!("{:?}", c.as_str())),
+        keyboard::Key::Unidentified => None,
+    }
+}
+
+/// Modifiers joined with `+`, e.g. `shift+ctrl`, or `-` for none
+fn parse_modifiers(s: &str) -> Result<keyboard::Modifiers, String> {
+    if s == "-" {
+        return Ok(keyboard::Modifiers::empty());
+    }
+    s.split('+')
+        .try_fold(keyboard::Modifiers::empty(), |acc, m| {
+            MODIFIERS
+                .iter()
+                .find(|(name, _)| *name == m)
+                .map(|(_, modifier)| acc | *modifier)
+                .ok_or_else(|| format!("unknown modifier: {m}"))
+        })
+}
+
+fn format_modifiers(modifiers: keyboard::Modifiers) -> String {
+    let names: Vec<_> = MODIFIERS
+        .iter()
+        .filter(|(_, m)| modifiers.contains(*m))
+        .map(|(name, _)| *name)
+        .collect();
+    if names.is_empty() {
+        "-".to_owned()
+    } else {
+        names.join("+")
+    }
+}
+
+/// Splits the key off the arguments of `keydown`/`keyup`. A quoted key ends at its closing
+/// quote, so that it can contain spaces.
+fn split_key(s: &str) -> (&str, &str) {
+    let end = if s.starts_with('"') {
+        let mut escaped = false;
+        s.char_indices()
+            .skip(1)
+            .find(|&(_, c)| {
+                let closing = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                closing
+            })
+            .map_or(s.len(), |(i, _)| i + 1)
+    } else {
+        s.find(' ').unwrap_or(s.len())
+    };
+    let (key, rest) = s.split_at(end);
+    (key, rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// Parses a string quoted the way `{:?}` formats it
+fn parse_quoted(s: &str) -> Result<String, String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string: {s}"))?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('u') => {
+                let code: String = chars
+                    .by_ref()
+                    .skip_while(|c| *c == '{')
+                    .take_while(|c| *c != '}')
+                    .collect();
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid escape: \\u{{{code}}}"))?
+            }
+            Some(c) => c,
+            None => return Err(format!("unfinished escape: {s}")),
+        });
+    }
+    Ok(out)
+}
+
+fn parse_num<N: std::str::FromStr>(s: Option<&str>) -> Result<N, String> {
+    let s = s.ok_or("missing argument")?;
+    s.parse().map_err(|_| format!("invalid number: {s}"))
+}
+
+/// Parses a script with one step per line. Empty lines and lines starting with `#` are ignored.
+///
+/// Supported commands:
+/// - `move <x> <y>` - move the cursor
+/// - `enter` / `leave` - move the cursor into or out of the window
+/// - `press <button>` / `release <button>` - mouse button, `left`, `right`, `middle`, `back`,
+///   `forward` or a number
+/// - `click <button>` - press and release
+/// - `scroll <x> <y>` - scroll by the given number of lines
+/// - `scroll-pixels <x> <y>` - scroll by the given number of pixels
+/// - `touch <phase> <finger> <x> <y>` - a finger touched (`press`), moved, was lifted (`lift`)
+///   or lost; fingers are numbered
+/// - `key <text>` - press and release a key producing the given text
+/// - `keydown <key> <modifiers> [<text>]` / `keyup <key> <modifiers>` - key is a name like
+///   `Enter` or `ArrowLeft`, or a quoted character like `"a"`; modifiers are e.g. `shift+ctrl`,
+///   or `-` for none; text is quoted too
+/// - `modifiers <modifiers>` - the pressed modifiers changed
+/// - `focus` / `unfocus` - the window gained or lost focus
+/// - `close` - the user asked to close the window; if the program agrees, the script ends
+/// - `resize <width> <height>`
+/// - `wait <milliseconds>`
+/// - `redraw` - process everything queued so far and render a frame
+/// - `frame <path>` - save the current frame as PNG
+///
+/// A line can start with a timestamp `@<milliseconds>`, counted from the start of the script,
+/// which waits until then before the step. Recordings (see [`crate::recording`]) are written
+/// in this form.
+pub fn parse_script<M>(src: &str) -> Result<Vec<Step<M>>, String> {
+    let mut steps = Vec::new();
+    let mut elapsed = 0;
+    for (i, line) in src.lines().enumerate() {
+        let mut line = line.trim();
+        if let Some(timestamped) = line.strip_prefix('@') {
+            let (timestamp, rest) = timestamped.split_once(' ').unwrap_or((timestamped, ""));
+            let timestamp: u64 =
+                parse_num(Some(timestamp)).map_err(|e| format!("line {}: {e}", i + 1))?;
+            if timestamp > elapsed {
+                steps.push(Step::Wait(Duration::from_millis(timestamp - elapsed)));
+                elapsed = timestamp;
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut args = rest.split_whitespace();
+        let mut parse_line = || -> Result<(), String> {
+            match cmd {
+                "move" => {
+                    let position = Point::new(parse_num(args.next())?, parse_num(args.next())?);
+                    steps.push(Step::Event(Event::Mouse(mouse::Event::CursorMoved {
+                        position,
+                    })));
+                }
+                "enter" => steps.push(Step::Event(Event::Mouse(mouse::Event::CursorEntered))),
+                "leave" => steps.push(Step::Event(Event::Mouse(mouse::Event::CursorLeft))),
+                "press" | "release" | "click" => {
+                    let button = parse_button(args.next().ok_or("missing button")?)?;
+                    if cmd != "release" {
+                        steps.push(Step::Event(Event::Mouse(mouse::Event::ButtonPressed(
+                            button,
+                        ))));
+                    }
+                    if cmd != "press" {
+                        steps.push(Step::Event(Event::Mouse(mouse::Event::ButtonReleased(
+                            button,
+                        ))));
+                    }
+                }
+                "scroll" | "scroll-pixels" => {
+                    let (x, y) = (parse_num(args.next())?, parse_num(args.next())?);
+                    let delta = if cmd == "scroll" {
+                        mouse::ScrollDelta::Lines { x, y }
+                    } else {
+                        mouse::ScrollDelta::Pixels { x, y }
+                    };
+                    steps.push(Step::Event(Event::Mouse(mouse::Event::WheelScrolled {
+                        delta,
+                    })));
+                }
+                "touch" => {
+                    let phase = args.next().ok_or("missing phase")?;
+                    let id = touch::Finger(parse_num(args.next())?);
+                    let position = Point::new(parse_num(args.next())?, parse_num(args.next())?);
+                    let event = match phase {
+                        "press" => touch::Event::FingerPressed { id, position },
+                        "move" => touch::Event::FingerMoved { id, position },
+                        "lift" => touch::Event::FingerLifted { id, position },
+                        "lost" => touch::Event::FingerLost { id, position },
+                        other => return Err(format!("unknown touch phase: {other}")),
+                    };
+                    steps.push(Step::Event(Event::Touch(event)));
+                }
+                "key" => {
+                    if rest.is_empty() {
+                        return Err("missing key text".to_owned());
+                    }
+                    let key = keyboard::Key::Character(rest.into());
+                    steps.push(Step::Event(Event::Keyboard(keyboard::Event::KeyPressed {
+                        key: key.clone(),
+                        modified_key: key.clone(),
+                        physical_key: keyboard::key::Physical::Unidentified(
+                            keyboard::key::NativeCode::Unidentified,
+                        ),
+                        location: keyboard::Location::Standard,
+                        modifiers: keyboard::Modifiers::default(),
+                        text: Some(rest.into()),
+                    })));
+                    steps.push(Step::Event(Event::Keyboard(keyboard::Event::KeyReleased {
+                        key,
+                        location: keyboard::Location::Standard,
+                        modifiers: keyboard::Modifiers::default(),
+                    })));
+                }
+                "keydown" | "keyup" => {
+                    let (key, rest) = split_key(rest);
+                    let key = parse_key(key)?;
+                    let mut parts = rest.splitn(2, ' ');
+                    let modifiers = parse_modifiers(
+                        parts
+                            .next()
+                            .filter(|s| !s.is_empty())
+                            .ok_or("missing modifiers")?,
+                    )?;
+                    let event = if cmd == "keydown" {
+                        let text = parts.next().map(parse_quoted).transpose()?;
+                        keyboard::Event::KeyPressed {
+                            modified_key: text.as_ref().map_or_else(
+                                || key.clone(),
+                                |t| keyboard::Key::Character(t.into()),
+                            ),
+                            key,
+                            physical_key: keyboard::key::Physical::Unidentified(
+                                keyboard::key::NativeCode::Unidentified,
+                            ),
+                            location: keyboard::Location::Standard,
+                            modifiers,
+                            text: text.map(Into::into),
+                        }
+                    } else {
+                        keyboard::Event::KeyReleased {
+                            key,
+                            location: keyboard::Location::Standard,
+                            modifiers,
+                        }
+                    };
+                    steps.push(Step::Event(Event::Keyboard(event)));
+                }
+                "modifiers" => {
+                    let modifiers = parse_modifiers(args.next().ok_or("missing modifiers")?)?;
+                    steps.push(Step::Event(Event::Keyboard(
+                        keyboard::Event::ModifiersChanged(modifiers),
+                    )));
+                }
+                "focus" => steps.push(Step::Event(Event::Window(window::Event::Focused))),
+                "unfocus" => steps.push(Step::Event(Event::Window(window::Event::Unfocused))),
+                "close" => steps.push(Step::Event(Event::Window(window::Event::CloseRequested))),
+                "resize" => {
+                    steps.push(Step::Resize(
+                        parse_num(args.next())?,
+                        parse_num(args.next())?,
+                    ));
+                }
+                "wait" => steps.push(Step::Wait(Duration::from_millis(parse_num(args.next())?))),
+                "redraw" => steps.push(Step::Redraw),
+                "frame" => {
+                    if rest.is_empty() {
+                        return Err("missing path".to_owned());
+                    }
+                    steps.push(Step::Frame(PathBuf::from(rest)));
+                }
+                other => return Err(format!("unknown command: {other}")),
+            }
+            Ok(())
+        };
+        parse_line().map_err(|e| format!("line {}: {e}", i + 1))?;
+    }
+    Ok(steps)
+}
+
+/// Formats the event as a script command, the inverse of [`parse_script`]. `None` for events
+/// that scripts can't express.
+pub(crate) fn format_event(event: &Event) -> Option<String> {
+    Some(match event {
+        Event::Mouse(mouse::Event::CursorMoved { position }) => {
+            format!("move {} {}", position.x, position.y)
+        }
+        Event::Mouse(mouse::Event::CursorEntered) => "enter".to_owned(),
+        Event::Mouse(mouse::Event::CursorLeft) => "leave".to_owned(),
+        Event::Mouse(mouse::Event::ButtonPressed(button)) => {
+            format!("press {}", format_button(*button))
+        }
+        Event::Mouse(mouse::Event::ButtonReleased(button)) => {
+            format!("release {}", format_button(*button))
+        }
+        Event::Mouse(mouse::Event::WheelScrolled {
+            delta: mouse::ScrollDelta::Lines { x, y },
+        }) => format!("scroll {x} {y}"),
+        Event::Mouse(mouse::Event::WheelScrolled {
+            delta: mouse::ScrollDelta::Pixels { x, y },
+        }) => format!("scroll-pixels {x} {y}"),
+        Event::Touch(event) => {
+            let (phase, id, position) = match event {
+                touch::Event::FingerPressed { id, position } => ("press", id, position),
+                touch::Event::FingerMoved { id, position } => ("move", id, position),
+                touch::Event::FingerLifted { id, position } => ("lift", id, position),
+                touch::Event::FingerLost { id, position } => ("lost", id, position),
+            };
+            format!("touch {phase} {} {} {}", id.0, position.x, position.y)
+        }
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            key,
+            modifiers,
+            text,
+            ..
+        }) => {
+            let mut line = format!(
+                "keydown {} {}",
+                format_key(key)?,
+                format_modifiers(*modifiers)
+            );
+            if let Some(text) = text {
+                line += &format!(" {:?}", text.as_str());
+            }
+            line
+        }
+        Event::Keyboard(keyboard::Event::KeyReleased { key, modifiers, .. }) => {
+            format!(
+                "keyup {} {}",
+                format_key(key)?,
+                format_modifiers(*modifiers)
+            )
+        }
+        Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+            format!("modifiers {}", format_modifiers(*modifiers))
+        }
+        Event::Window(window::Event::Focused) => "focus".to_owned(),
+        Event::Window(window::Event::Unfocused) => "unfocus".to_owned(),
+        Event::Window(window::Event::CloseRequested) => "close".to_owned(),
+        _ => return None,
+    })
+}
+
+/// Runs the program headless, driven by the script at `path` (see [`parse_script`])
+pub fn run_script_file<T: ProgramExt>(
+    create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static)) -> T,
+    options: &WindowOptions,
+    path: &Path,
+) -> Result<(), Error> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| Error::Script(format!("reading {}: {e}", path.display())))?;
+    let steps = parse_script(&src).map_err(Error::Script)?;
+    let mut headless = Headless::new(create_app, options, DEFAULT_SIZE.width, DEFAULT_SIZE.height)?;
+    headless
+        .run_steps(steps)
+        .map_err(|e| Error::Script(format!("saving frame: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use iced_runtime::{Program, Task};
+    use iced_widget::core::Length;
+    use iced_widget::{Theme, container};
+
+    use super::*;
+    use crate::Element;
+
+    const SCRIPT: &str = r#"move 10.5 20
+enter
+leave
+press left
+release 4
+scroll 0 -1
+scroll-pixels 2.5 0
+touch press 1 3 4
+touch lift 1 3 4
+keydown "a" shift "A"
+keydown "\"" - "\\"
+keydown "\u{301}" - "\u{301}"
+keydown " " - " "
+keydown Enter ctrl+alt "\n"
+keyup ArrowLeft shift+logo
+modifiers shift+ctrl
+focus
+unfocus
+close"#;
+
+    fn format_events(steps: &[Step<()>]) -> Vec<String> {
+        steps
+            .iter()
+            .map(|step| match step {
+                Step::Event(event) => format_event(event).expect("event has a script command"),
+                other => panic!("not an event: {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn script_round_trip() {
+        let steps = parse_script(SCRIPT).unwrap();
+        assert_eq!(format_events(&steps), SCRIPT.lines().collect::<Vec<_>>());
+
+        let formatted = format_events(&steps).join("\n");
+        let reparsed = parse_script::<()>(&formatted).unwrap();
+        for (step, reparsed) in steps.iter().zip(&reparsed) {
+            let (Step::Event(event), Step::Event(reparsed)) = (step, reparsed) else {
+                unreachable!();
+            };
+            assert_eq!(event, reparsed);
+        }
+    }
+
+    #[test]
+    fn script_escapes() {
+        let steps = parse_script::<()>(r#"keydown "\u{1F600}" - "\t\u{e9}\\""#).unwrap();
+        let [
+            Step::Event(Event::Keyboard(keyboard::Event::KeyPressed {
+                key,
+                modified_key,
+                text,
+                ..
+            })),
+        ] = steps.as_slice()
+        else {
+            panic!("unexpected steps: {steps:?}");
+        };
+        assert_eq!(*key, keyboard::Key::Character("\u{1F600}".into()));
+        assert_eq!(*modified_key, keyboard::Key::Character("\t\u{e9}\\".into()));
+        assert_eq!(text.as_deref(), Some("\t\u{e9}\\"));
+    }
+
+    #[test]
+    fn script_timestamps() {
+        let steps =
+            parse_script::<()>("@100 focus\n@100 # comment\n@40 unfocus\n@250\nredraw").unwrap();
+        let waits: Vec<_> = steps
+            .iter()
+            .map(|step| match step {
+                Step::Wait(duration) => Some(duration.as_millis()),
+                _ => None,
+            })
+            .collect();
+        // timestamps in the past don't wait, and a timestamp alone still waits
+        assert_eq!(waits, [Some(100), None, None, Some(150), None]);
+    }
+
+    #[test]
+    fn script_errors() {
+        let error = |src| parse_script::<()>(src).unwrap_err();
+        assert_eq!(
+            error("focus\n\n# ok\nbogus"),
+            "line 4: unknown command: bogus"
+        );
+        assert_eq!(error("move 1"), "line 1: missing argument");
+        assert_eq!(error("redraw\n@x focus"), "line 2: invalid number: x");
+        assert_eq!(error("keydown Foo -"), "line 1: unknown key: Foo");
+        assert_eq!(error(r#"keydown " ""#), "line 1: missing modifiers");
+        assert_eq!(
+            error("modifiers shift+hyper"),
+            "line 1: unknown modifier: hyper"
+        );
+        assert_eq!(
+            error(r#"keydown "\u{zz}" -"#),
+            r"line 1: invalid escape: \u{zz}"
+        );
+    }
+
+    #[derive(Debug)]
+    struct Fill {
+        color: Color,
+        closable: bool,
+    }
+
+    #[derive(Debug)]
+    enum Message {
+        Paint(Color),
+    }
+
+    impl Program for Fill {
+        type Message = Message;
+        type Renderer = Renderer;
+        type Theme = Theme;
+
+        fn update(&mut self, Message::Paint(color): Message) -> Task<Message> {
+            self.color = color;
+            Task::none()
+        }
+
+        fn view(&self) -> Element<'_, Message> {
+            let color = self.color;
+            container("")
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .style(move |_| container::Style::default().background(color))
+                .into()
+        }
+    }
+
+    impl ProgramExt for Fill {
+        fn close_requested(&mut self) -> bool {
+            self.closable
+        }
+    }
+
+    fn fill(closable: bool) -> Headless<Fill> {
+        let create_app = |_: &(dyn (Fn() -> SendMsgFn<Message>) + Send + 'static)| Fill {
+            color: Color::from_rgb8(255, 0, 0),
+            closable,
+        };
+        Headless::new(create_app, &WindowOptions::default(), 40, 30).unwrap()
+    }
+
+    #[test]
+    fn renders_frames() {
+        let mut headless = fill(true);
+        let frame = headless.frame();
+        assert_eq!(frame.dimensions(), (40, 30));
+        assert_eq!(frame.get_pixel(20, 15).0, [255, 0, 0, 255]);
+
+        headless.queue_message(Message::Paint(Color::from_rgb8(0, 0, 255)));
+        headless.resize(0, 10);
+        let frame = headless.frame();
+        assert_eq!(frame.dimensions(), (1, 10));
+        assert_eq!(frame.get_pixel(0, 5).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn close_ends_the_script() {
+        let blue = Color::from_rgb8(0, 0, 255);
+        for closable in [false, true] {
+            let mut headless = fill(closable);
+            headless
+                .run_steps([
+                    Step::Event(Event::Window(window::Event::CloseRequested)),
+                    Step::Message(Message::Paint(blue)),
+                    Step::Redraw,
+                ])
+                .unwrap();
+            assert_eq!(headless.has_exited(), closable);
+            assert_eq!(headless.program().color == blue, !closable);
+        }
+    }
+}
@@ -1,24 +1,29 @@
 use std::ffi::{self, CString};
-use std::fmt::Debug;
 use std::mem::MaybeUninit;
 use std::ptr::NonNull;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use helenos_ui::util::pointer_init;
-use iced_runtime::Program;
 use iced_widget::core::mouse::{self, Cursor, Interaction};
-use iced_widget::core::{Event, Point};
-use iced_widget::{Renderer, Theme};
+use iced_widget::core::{Event, Point, Rectangle, keyboard, window};
 use raw_window_handle::{
     DisplayHandle, HasDisplayHandle, HasWindowHandle, HelenOSDisplayHandle, HelenOSWindowHandle,
     RawDisplayHandle, RawWindowHandle, WindowHandle,
 };
 
-use crate::{AppInner, ProgramExt, SendMsgFn, WindowOptions};
+use super::helenos_keys as conversion;
+use crate::scheduler::Wakeup;
+use crate::thread::Supervisor;
+use crate::{
+    AppInner, Error, ProgramExt, SendMsgFn, WindowOptions, channel, clipboard, error, info, warn,
+};
 
 #[derive(Debug)]
 pub struct Window {
     raw: NonNull<helenos_ui::ui_window_t>,
+    // decorations the window was created with, to compute its size from the drawable area
+    style: helenos_ui::ui_wdecor_style_t,
 }
 
 unsafe impl Send for Window {}
@@ -64,35 +69,80 @@ impl Window {
         }
     }
 
+    /// The display server doesn't report pixel density, larger scale can be set
+    /// with [`WindowOptions::scale`]
     pub fn scale_factor(&self) -> f64 {
         1.0
     }
 }
 
+/// Only a few stock cursors exist, the other interactions show the arrow
 pub fn set_cursor(w: &Window, interaction: Interaction) {
+    use helenos_ui::ui_stock_cursor_t as Cursor;
+
     let curs = match interaction {
-        Interaction::Pointer => helenos_ui::ui_stock_cursor_t::ui_curs_pointer,
-        Interaction::Text => helenos_ui::ui_stock_cursor_t::ui_curs_ibeam,
-        _ => helenos_ui::ui_stock_cursor_t::ui_curs_arrow,
+        Interaction::Pointer => Cursor::ui_curs_pointer,
+        Interaction::Text => Cursor::ui_curs_ibeam,
+        Interaction::ResizingHorizontally => Cursor::ui_curs_size_lr,
+        Interaction::ResizingVertically => Cursor::ui_curs_size_ud,
+        Interaction::ResizingDiagonallyUp => Cursor::ui_curs_size_urdl,
+        Interaction::ResizingDiagonallyDown => Cursor::ui_curs_size_uldr,
+        _ => Cursor::ui_curs_arrow,
     };
     unsafe { helenos_ui::ui_window_set_ctl_cursor(w.raw.as_ptr(), curs) };
 }
 
+/// The UI library has no input methods, text is composed by the keyboard layouts
+pub fn set_ime_area(_w: &Window, _area: Option<Rectangle>) {}
+
+/// Applies the changes the UI library supports: resizing, maximizing and minimizing
+pub fn change_window(w: &Window, request: crate::window::Request, scale_factor: f64) {
+    use crate::window::Request;
+
+    let raw = w.raw.as_ptr();
+    match request {
+        Request::Resize(size) => unsafe {
+            let ui = helenos_ui::ui_window_get_ui(raw);
+            match window_rect(ui, w.style, physical_size(size, scale_factor)) {
+                Ok(mut rect) => {
+                    let rc = helenos_ui::ui_window_resize(raw, &mut rect);
+                    if rc != 0 {
+                        error!("Error resizing window: {rc}");
+                    }
+                }
+                Err(e) => error!("Error resizing window: {e}"),
+            }
+        },
+        Request::Maximize(true) => unsafe { helenos_ui::ui_window_def_maximize(raw) },
+        Request::Maximize(false) => unsafe { helenos_ui::ui_window_def_unmaximize(raw) },
+        Request::Minimize(true) => unsafe { helenos_ui::ui_window_def_minimize(raw) },
+        other => warn!("Unsupported window change on HelenOS: {other:?}"),
+    }
+}
+
+/// HelenOS has no clipboard accessible through the UI library, so only an in-process one is used
+pub fn system_clipboard() -> Option<Box<dyn clipboard::System>> {
+    None
+}
+
 struct App<T>
 where
-    T: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static,
+    T: ProgramExt,
 {
     inner: AppInner<T>,
     quit: bool,
     window: Arc<Window>,
+    wakeup: Arc<Wakeup>,
     _pin: std::marker::PhantomPinned,
 
     cursor: Cursor,
+    // modifiers of the last key event, to report their changes
+    modifiers: keyboard::Modifiers,
 }
 
 impl<T> std::fmt::Debug for App<T>
 where
-    T: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static,
+    T: ProgramExt,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("App")
@@ -108,7 +158,7 @@ trait CallbacksProvider {
 
 impl<T> CallbacksProvider for App<T>
 where
-    T: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static,
+    T: ProgramExt,
 {
     const CALLBACKS: helenos_ui::ui_window_cb_t = helenos_ui::ui_window_cb_t {
         sysmenu: None,
@@ -117,11 +167,11 @@ where
         unmaximize: None,
         resize: None,
         close: Some(Self::close_event),
-        focus: None,
-        kbd: None,
+        focus: Some(Self::focus_event),
+        kbd: Some(Self::kbd_event),
         paint: Some(Self::paint_event),
         pos: Some(Self::pos_event),
-        unfocus: None,
+        unfocus: Some(Self::unfocus_event),
     };
 }
 
@@ -129,7 +179,7 @@ type Arg<T> = Mutex<App<T>>;
 
 impl<T> App<T>
 where
-    T: Debug + Program<Theme = Theme, Renderer = Renderer> + 'static,
+    T: ProgramExt,
 {
     unsafe extern "C" fn pos_event(
         window: *mut helenos_ui::ui_window_t,
@@ -151,10 +201,10 @@ where
                 let app_rect =
                     pointer_init(|p| unsafe { helenos_ui::ui_window_get_app_rect(window, p) })
                         .unwrap();
-                let p = Point {
+                let p = app.inner.to_logical(Point {
                     x: (ev.hpos as i32 - app_rect.p0.x) as f32,
                     y: (ev.vpos as i32 - app_rect.p0.y) as f32,
-                };
+                });
                 app.cursor = Cursor::Available(p);
                 mouse::Event::CursorMoved { position: p }
             }
@@ -164,13 +214,72 @@ where
                 return;
             }
         };
-        app.inner.program.queue_event(Event::Mouse(ev));
+        app.inner.queue_event(Event::Mouse(ev));
+        app.wakeup.wake();
+    }
+
+    unsafe extern "C" fn kbd_event(
+        _window: *mut helenos_ui::ui_window_t,
+        app: *mut ffi::c_void,
+        ev: *mut helenos_ui::kbd_event_t,
+    ) {
+        type Evt = helenos_ui::kbd_event_type_t;
+        let app = unsafe { &*(app as *const Arg<T>) };
+        let ev = unsafe { &*ev };
+        let mut app = app.lock().unwrap();
+        let pressed = match ev.type_ {
+            Evt::KEY_PRESS => true,
+            Evt::KEY_RELEASE => false,
+            _ => {
+                return;
+            }
+        };
+        let modifiers = conversion::modifiers(ev.mods.0 as u32);
+        if modifiers != app.modifiers {
+            app.modifiers = modifiers;
+            app.inner
+                .queue_event(Event::Keyboard(keyboard::Event::ModifiersChanged(
+                    modifiers,
+                )));
+        }
+        let ev = conversion::key_event(pressed, ev.key.0 as u32, ev.mods.0 as u32, ev.c);
+        app.inner.queue_event(Event::Keyboard(ev));
+        app.wakeup.wake();
     }
 
     unsafe extern "C" fn close_event(_window: *mut helenos_ui::ui_window_t, app: *mut ffi::c_void) {
         let app = unsafe { &*(app as *const Arg<T>) };
         let app = &mut *app.lock().unwrap();
-        app.quit = true;
+        if app.inner.close_requested() {
+            app.quit = true;
+        }
+        app.wakeup.wake();
+    }
+
+    unsafe extern "C" fn focus_event(
+        wnd: *mut helenos_ui::ui_window_t,
+        app: *mut ffi::c_void,
+        nfocus: ffi::c_uint,
+    ) {
+        // keep the default handling, which updates the window decoration
+        unsafe { helenos_ui::ui_window_def_focus(wnd, nfocus) };
+        let app = unsafe { &*(app as *const Arg<T>) };
+        let mut app = app.lock().unwrap();
+        app.inner.queue_event(Event::Window(window::Event::Focused));
+        app.wakeup.wake();
+    }
+
+    unsafe extern "C" fn unfocus_event(
+        wnd: *mut helenos_ui::ui_window_t,
+        app: *mut ffi::c_void,
+        nfocus: ffi::c_uint,
+    ) {
+        unsafe { helenos_ui::ui_window_def_unfocus(wnd, nfocus) };
+        let app = unsafe { &*(app as *const Arg<T>) };
+        let mut app = app.lock().unwrap();
+        app.inner
+            .queue_event(Event::Window(window::Event::Unfocused));
+        app.wakeup.wake();
     }
 
     unsafe extern "C" fn paint_event(
@@ -191,90 +300,149 @@ where
 
 pub fn run<T: ProgramExt>(
     create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static)) -> T + 'static,
-    WindowOptions { caption, maximized }: WindowOptions,
-) {
+    options: WindowOptions,
+) -> Result<(), Error> {
     unsafe {
         let ui = pointer_init(|ptr| {
             helenos_ui::ui_create(helenos_ui::UI_DISPLAY_DEFAULT.as_ptr() as *const _, ptr)
         })
-        .expect("Failed to open display");
-        let init_w = 300;
-        let init_h = 400;
+        .map_err(|e| Error::Display(format!("{e:?}")))?;
 
-        let mut wndparams = pointer_init(|ptr| helenos_ui::ui_wnd_params_init(ptr)).unwrap();
-        wndparams.style |= helenos_ui::ui_wdecor_style_t::ui_wds_resizable
-            | helenos_ui::ui_wdecor_style_t::ui_wds_maximize_btn;
+        let result = create_window(ui, &options)
+            .and_then(|window| run_app_in_window(window, create_app, &options));
+        helenos_ui::ui_destroy(ui);
+        result
+    }
+}
 
+/// Default size of the drawable area, if not set in the options
+const DEFAULT_SIZE: (u32, u32) = (300, 400);
+const DEFAULT_MIN_SIZE: (u32, u32) = (100, 100);
+
+/// Rectangle of the whole window, including decorations, around a drawable area of the given size
+unsafe fn window_rect(
+    ui: *mut helenos_ui::ui_t,
+    style: helenos_ui::ui_wdecor_style_t,
+    (width, height): (u32, u32),
+) -> Result<helenos_ui::gfx_rect_t, Error> {
+    unsafe {
         // helenos boilerplate code to create a window rectangle
         let mut rect1 = helenos_ui::gfx_rect_t {
             p0: helenos_ui::gfx_coord2_t { x: 0, y: 0 },
             p1: helenos_ui::gfx_coord2_t {
-                x: init_w as i32,
-                y: init_h as i32,
+                x: width as i32,
+                y: height as i32,
             },
         };
-        let mut rect2 = pointer_init(|ptr| {
-            helenos_ui::ui_wdecor_rect_from_app(ui, wndparams.style, &mut rect1, ptr)
-        })
-        .unwrap();
+        let mut rect2 =
+            pointer_init(|ptr| helenos_ui::ui_wdecor_rect_from_app(ui, style, &mut rect1, ptr))
+                .map_err(|e| Error::Window(format!("{e:?}")))?;
         let mut offset = rect2.p0;
-        helenos_ui::gfx_rect_rtranslate(&mut offset, &mut rect2, &mut wndparams.rect);
+        let mut rect = pointer_init(|ptr| {
+            helenos_ui::gfx_rect_rtranslate(&mut offset, &mut rect2, ptr);
+        })
+        .map_err(|e| Error::Window(format!("{e:?}")))?;
+        Ok(rect)
+    }
+}
+
+fn physical_size(size: iced_widget::core::Size, scale: f64) -> (u32, u32) {
+    let size = crate::window::to_physical(size, scale);
+    (size.width, size.height)
+}
+
+unsafe fn create_window(
+    ui: *mut helenos_ui::ui_t,
+    options: &WindowOptions,
+) -> Result<Window, Error> {
+    unsafe {
+        let scale = options.scale.unwrap_or(1.0);
 
-        wndparams.min_size.x = 100;
-        wndparams.min_size.y = 100;
-        let string = CString::new(caption.into_owned()).unwrap();
+        let mut wndparams = pointer_init(|ptr| helenos_ui::ui_wnd_params_init(ptr))
+            .map_err(|e| Error::Window(format!("{e:?}")))?;
+        if !options.decorations {
+            wndparams.style = helenos_ui::ui_wdecor_style_t::ui_wds_none;
+        }
+        if options.resizable {
+            wndparams.style |= helenos_ui::ui_wdecor_style_t::ui_wds_resizable
+                | helenos_ui::ui_wdecor_style_t::ui_wds_maximize_btn;
+        }
+        if options.fullscreen {
+            wndparams.placement = helenos_ui::ui_wnd_placement_t::ui_wnd_place_full_screen;
+        }
+
+        let size = options
+            .size
+            .map_or(DEFAULT_SIZE, |size| physical_size(size, scale));
+        wndparams.rect = window_rect(ui, wndparams.style, size)?;
+
+        let (min_width, min_height) = options
+            .min_size
+            .map_or(DEFAULT_MIN_SIZE, |size| physical_size(size, scale));
+        wndparams.min_size.x = min_width as i32;
+        wndparams.min_size.y = min_height as i32;
+        let string = CString::new(&*options.caption)
+            .map_err(|_| Error::Window("caption contains a NUL character".to_owned()))?;
         wndparams.caption = string.as_ptr();
 
         let window = pointer_init(|ptr| helenos_ui::ui_window_create(ui, &mut wndparams, ptr))
-            .expect("Failed to create window");
-        if maximized {
+            .map_err(|e| Error::Window(format!("{e:?}")))?;
+        if options.maximized {
             helenos_ui::ui_window_def_maximize(window);
         }
 
-        run_app_in_window(
-            Window {
-                raw: NonNull::new(window).unwrap(),
-            },
-            create_app,
-        );
-        helenos_ui::ui_destroy(ui);
-    }
-}
-
-#[derive(Debug)]
-/// An unfortunately-necessary wrapper to make the pointers `Send`,
-/// so that we can use them in a Send closure
-struct SendAppPtr<T: ProgramExt>(*const Arg<T>, *mut helenos_ui::ui_window_t);
-unsafe impl<T: ProgramExt> Send for SendAppPtr<T> {}
-impl<T: ProgramExt> Clone for SendAppPtr<T> {
-    fn clone(&self) -> Self {
-        SendAppPtr(self.0, self.1)
+        Ok(Window {
+            raw: NonNull::new(window).unwrap(),
+            style: wndparams.style,
+        })
     }
 }
 
 fn run_app_in_window<T: ProgramExt>(
     window: Window,
     create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static)) -> T + 'static,
-) {
+    options: &WindowOptions,
+) -> Result<(), Error> {
     let window_arc = Arc::new(window);
     let mut app = std::pin::pin!(MaybeUninit::uninit());
-    let app_ptr = SendAppPtr(app.as_ptr(), window_arc.raw.as_ptr());
-    let create_send_msg = move || {
-        let app_ptr = app_ptr.clone();
-        Box::new(move |msg: T::Message| {
-            let app_ptr = app_ptr.clone();
-            let app = unsafe { &*(app_ptr.0) };
-            app.lock().unwrap().inner.program.queue_message(msg);
-        }) as SendMsgFn<T::Message>
+    let wakeup = Arc::new(Wakeup::default());
+    let (sender, receiver) = channel::channel({
+        let wakeup = wakeup.clone();
+        move || wakeup.wake()
+    });
+    let create_send_msg = {
+        let sender = sender.clone();
+        move || sender.send_msg_fn()
+    };
+
+    let supervisor = Supervisor::default();
+    let program = {
+        let _entered = supervisor.enter();
+        create_app(&create_send_msg)
     };
 
+    let inner = match AppInner::new(
+        window_arc.clone(),
+        program,
+        options,
+        (sender, receiver),
+        supervisor,
+    ) {
+        Ok(inner) => inner,
+        Err(e) => {
+            unsafe { helenos_ui::ui_window_destroy(window_arc.raw.as_ptr()) };
+            return Err(e);
+        }
+    };
     app.set(MaybeUninit::new(Mutex::new(App {
         window: window_arc.clone(),
-        inner: AppInner::new(window_arc.clone(), create_app(&create_send_msg)),
+        wakeup: wakeup.clone(),
+        inner,
         quit: false,
         _pin: std::marker::PhantomPinned,
 
         cursor: Cursor::Unavailable,
+        modifiers: keyboard::Modifiers::empty(),
     })));
     let app = unsafe { app.assume_init_ref() };
 
@@ -292,21 +460,27 @@ fn run_app_in_window<T: ProgramExt>(
     }
 
     loop {
-        std::thread::sleep(std::time::Duration::from_millis(1000 / 30));
-        let app = app.lock().unwrap();
+        let mut app = app.lock().unwrap();
+        if app.inner.drain_actions().is_break() {
+            app.quit = true;
+        }
         if app.quit {
             break;
         }
-        if !app.inner.program.is_queue_empty() {
-            // drop the lock, process events and repaint
-            drop(app);
+        let next_redraw = app.inner.next_redraw();
+        // drop the lock, so that the callbacks can queue events while we sleep
+        drop(app);
+        if next_redraw.is_some_and(|at| at <= Instant::now()) {
             unsafe {
                 helenos_ui::ui_window_paint(window_arc.raw.as_ptr());
             }
+        } else {
+            wakeup.wait_until(next_redraw);
         }
     }
-    println!("Window closed, quitting...");
-    app.lock().unwrap().inner.program.program().stop();
+    info!("Window closed, quitting...");
+    app.lock().unwrap().inner.stop();
 
     unsafe { helenos_ui::ui_window_destroy(window_arc.raw.as_ptr()) };
+    Ok(())
 }
@@ -0,0 +1,416 @@
+//! Conversion of HelenOS keyboard events into iced ones.
+//!
+//! The key codes and modifiers are copied from `io/keycode.h` of HelenOS. They don't depend on
+//! the HelenOS bindings, so that the conversion is tested on other systems too.
+
+use iced_widget::core::SmolStr;
+use iced_widget::core::keyboard::key::{Code, Named, NativeCode, Physical};
+use iced_widget::core::keyboard::{self, Key, Location, Modifiers};
+
+// `keymod_t`
+const KM_LSHIFT: u32 = 0x001;
+const KM_RSHIFT: u32 = 0x002;
+const KM_LCTRL: u32 = 0x004;
+const KM_RCTRL: u32 = 0x008;
+const KM_LALT: u32 = 0x010;
+const KM_RALT: u32 = 0x020;
+
+// `keycode_t`, the keys are named by their position on a US keyboard
+const KC_BACKTICK: u32 = 1;
+const KC_1: u32 = 2;
+const KC_0: u32 = 11;
+const KC_MINUS: u32 = 12;
+const KC_EQUALS: u32 = 13;
+const KC_BACKSPACE: u32 = 14;
+const KC_TAB: u32 = 15;
+const KC_Q: u32 = 16;
+const KC_P: u32 = 25;
+const KC_LBRACKET: u32 = 26;
+const KC_RBRACKET: u32 = 27;
+const KC_CAPS_LOCK: u32 = 28;
+const KC_A: u32 = 29;
+const KC_L: u32 = 37;
+const KC_SEMICOLON: u32 = 38;
+const KC_QUOTE: u32 = 39;
+const KC_BACKSLASH: u32 = 40;
+const KC_ENTER: u32 = 41;
+const KC_LSHIFT: u32 = 42;
+const KC_Z: u32 = 43;
+const KC_M: u32 = 49;
+const KC_COMMA: u32 = 50;
+const KC_PERIOD: u32 = 51;
+const KC_SLASH: u32 = 52;
+const KC_RSHIFT: u32 = 53;
+const KC_LCTRL: u32 = 54;
+const KC_LALT: u32 = 55;
+const KC_SPACE: u32 = 56;
+const KC_RALT: u32 = 57;
+const KC_RCTRL: u32 = 58;
+const KC_ESCAPE: u32 = 59;
+const KC_F1: u32 = 60;
+const KC_F12: u32 = 71;
+const KC_PRTSCR: u32 = 72;
+const KC_SCROLL_LOCK: u32 = 73;
+const KC_PAUSE: u32 = 74;
+const KC_INSERT: u32 = 75;
+const KC_HOME: u32 = 76;
+const KC_PAGE_UP: u32 = 77;
+const KC_DELETE: u32 = 78;
+const KC_END: u32 = 79;
+const KC_PAGE_DOWN: u32 = 80;
+const KC_UP: u32 = 81;
+const KC_LEFT: u32 = 82;
+const KC_DOWN: u32 = 83;
+const KC_RIGHT: u32 = 84;
+const KC_NUM_LOCK: u32 = 85;
+const KC_NSLASH: u32 = 86;
+const KC_NTIMES: u32 = 87;
+const KC_NMINUS: u32 = 88;
+const KC_NPLUS: u32 = 89;
+const KC_NENTER: u32 = 90;
+const KC_N7: u32 = 91;
+const KC_N8: u32 = 92;
+const KC_N9: u32 = 93;
+const KC_N4: u32 = 94;
+const KC_N5: u32 = 95;
+const KC_N6: u32 = 96;
+const KC_N1: u32 = 97;
+const KC_N2: u32 = 98;
+const KC_N3: u32 = 99;
+const KC_N0: u32 = 100;
+const KC_NPERIOD: u32 = 101;
+
+// the rows of letters and digits, in the order of their key codes
+const DIGITS: [Code; 10] = [
+    Code::Digit1,
+    Code::Digit2,
+    Code::Digit3,
+    Code::Digit4,
+    Code::Digit5,
+    Code::Digit6,
+    Code::Digit7,
+    Code::Digit8,
+    Code::Digit9,
+    Code::Digit0,
+];
+const ROW_Q: [Code; 10] = [
+    Code::KeyQ,
+    Code::KeyW,
+    Code::KeyE,
+    Code::KeyR,
+    Code::KeyT,
+    Code::KeyY,
+    Code::KeyU,
+    Code::KeyI,
+    Code::KeyO,
+    Code::KeyP,
+];
+const ROW_A: [Code; 9] = [
+    Code::KeyA,
+    Code::KeyS,
+    Code::KeyD,
+    Code::KeyF,
+    Code::KeyG,
+    Code::KeyH,
+    Code::KeyJ,
+    Code::KeyK,
+    Code::KeyL,
+];
+const ROW_Z: [Code; 7] = [
+    Code::KeyZ,
+    Code::KeyX,
+    Code::KeyC,
+    Code::KeyV,
+    Code::KeyB,
+    Code::KeyN,
+    Code::KeyM,
+];
+const FUNCTION_KEYS: [(Code, Named); 12] = [
+    (Code::F1, Named::F1),
+    (Code::F2, Named::F2),
+    (Code::F3, Named::F3),
+    (Code::F4, Named::F4),
+    (Code::F5, Named::F5),
+    (Code::F6, Named::F6),
+    (Code::F7, Named::F7),
+    (Code::F8, Named::F8),
+    (Code::F9, Named::F9),
+    (Code::F10, Named::F10),
+    (Code::F11, Named::F11),
+    (Code::F12, Named::F12),
+];
+
+/// Converts a key press or release. `c` is the character typed by the key according to the
+/// keyboard layout and the modifiers, or 0 for keys typing nothing. The layouts of HelenOS type
+/// nothing while Ctrl or Alt is held, so the key is then the character it has on a US keyboard.
+pub fn key_event(pressed: bool, keycode: u32, mods: u32, c: u32) -> keyboard::Event {
+    let modifiers = self::modifiers(mods);
+    let text = char::from_u32(c)
+        .filter(|c| *c != '\0')
+        .map(|c| SmolStr::from(c.to_string()));
+    let (code, named) = self::code(keycode);
+    let location = self::location(keycode);
+    // the numeric block types digits only with Num Lock, otherwise it moves the cursor
+    let named = named.filter(|_| location != Location::Numpad || text.is_none());
+
+    // `key` ignores Shift (e.g. Shift+a is still "a"), `modified_key` and `text` include it
+    let (key, modified_key) = match (named, &text) {
+        (Some(named), _) => (Key::Named(named), Key::Named(named)),
+        (None, Some(text)) => (
+            Key::Character(text.to_lowercase().into()),
+            Key::Character(text.clone()),
+        ),
+        (None, None) => match us_char(keycode) {
+            Some(c) => {
+                let c = Key::Character(SmolStr::from(c.to_string()));
+                (c.clone(), c)
+            }
+            None => (Key::Unidentified, Key::Unidentified),
+        },
+    };
+    let physical_key = match code {
+        Some(code) => Physical::Code(code),
+        None => Physical::Unidentified(NativeCode::Unidentified),
+    };
+
+    if pressed {
+        keyboard::Event::KeyPressed {
+            key,
+            modified_key,
+            physical_key,
+            location,
+            modifiers,
+            text,
+        }
+    } else {
+        keyboard::Event::KeyReleased {
+            key,
+            location,
+            modifiers,
+        }
+    }
+}
+
+/// Converts the `keymod_t` flags, HelenOS has no logo key
+pub fn modifiers(mods: u32) -> Modifiers {
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(Modifiers::SHIFT, mods & (KM_LSHIFT | KM_RSHIFT) != 0);
+    modifiers.set(Modifiers::CTRL, mods & (KM_LCTRL | KM_RCTRL) != 0);
+    modifiers.set(Modifiers::ALT, mods & (KM_LALT | KM_RALT) != 0);
+    modifiers
+}
+
+fn location(keycode: u32) -> Location {
+    match keycode {
+        KC_LSHIFT | KC_LCTRL | KC_LALT => Location::Left,
+        KC_RSHIFT | KC_RCTRL | KC_RALT => Location::Right,
+        KC_NSLASH..=KC_NPERIOD => Location::Numpad,
+        _ => Location::Standard,
+    }
+}
+
+/// The physical key, and the named key if it doesn't type a character
+fn code(keycode: u32) -> (Option<Code>, Option<Named>) {
+    let index = |first: u32| (keycode - first) as usize;
+    let (code, named) = match keycode {
+        KC_1..=KC_0 => (DIGITS[index(KC_1)], None),
+        KC_Q..=KC_P => (ROW_Q[index(KC_Q)], None),
+        KC_A..=KC_L => (ROW_A[index(KC_A)], None),
+        KC_Z..=KC_M => (ROW_Z[index(KC_Z)], None),
+        KC_F1..=KC_F12 => {
+            let (code, named) = FUNCTION_KEYS[index(KC_F1)];
+            (code, Some(named))
+        }
+        KC_BACKTICK => (Code::Backquote, None),
+        KC_MINUS => (Code::Minus, None),
+        KC_EQUALS => (Code::Equal, None),
+        KC_BACKSPACE => (Code::Backspace, Some(Named::Backspace)),
+        KC_TAB => (Code::Tab, Some(Named::Tab)),
+        KC_LBRACKET => (Code::BracketLeft, None),
+        KC_RBRACKET => (Code::BracketRight, None),
+        KC_CAPS_LOCK => (Code::CapsLock, Some(Named::CapsLock)),
+        KC_SEMICOLON => (Code::Semicolon, None),
+        KC_QUOTE => (Code::Quote, None),
+        KC_BACKSLASH => (Code::Backslash, None),
+        KC_ENTER => (Code::Enter, Some(Named::Enter)),
+        KC_LSHIFT => (Code::ShiftLeft, Some(Named::Shift)),
+        KC_RSHIFT => (Code::ShiftRight, Some(Named::Shift)),
+        KC_COMMA => (Code::Comma, None),
+        KC_PERIOD => (Code::Period, None),
+        KC_SLASH => (Code::Slash, None),
+        KC_LCTRL => (Code::ControlLeft, Some(Named::Control)),
+        KC_RCTRL => (Code::ControlRight, Some(Named::Control)),
+        KC_LALT => (Code::AltLeft, Some(Named::Alt)),
+        KC_RALT => (Code::AltRight, Some(Named::Alt)),
+        KC_SPACE => (Code::Space, Some(Named::Space)),
+        KC_ESCAPE => (Code::Escape, Some(Named::Escape)),
+        KC_PRTSCR => (Code::PrintScreen, Some(Named::PrintScreen)),
+        KC_SCROLL_LOCK => (Code::ScrollLock, Some(Named::ScrollLock)),
+        KC_PAUSE => (Code::Pause, Some(Named::Pause)),
+        KC_INSERT => (Code::Insert, Some(Named::Insert)),
+        KC_HOME => (Code::Home, Some(Named::Home)),
+        KC_PAGE_UP => (Code::PageUp, Some(Named::PageUp)),
+        KC_DELETE => (Code::Delete, Some(Named::Delete)),
+        KC_END => (Code::End, Some(Named::End)),
+        KC_PAGE_DOWN => (Code::PageDown, Some(Named::PageDown)),
+        KC_UP => (Code::ArrowUp, Some(Named::ArrowUp)),
+        KC_LEFT => (Code::ArrowLeft, Some(Named::ArrowLeft)),
+        KC_DOWN => (Code::ArrowDown, Some(Named::ArrowDown)),
+        KC_RIGHT => (Code::ArrowRight, Some(Named::ArrowRight)),
+        KC_NUM_LOCK => (Code::NumLock, Some(Named::NumLock)),
+        KC_NSLASH => (Code::NumpadDivide, None),
+        KC_NTIMES => (Code::NumpadMultiply, None),
+        KC_NMINUS => (Code::NumpadSubtract, None),
+        KC_NPLUS => (Code::NumpadAdd, None),
+        KC_NENTER => (Code::NumpadEnter, Some(Named::Enter)),
+        KC_N7 => (Code::Numpad7, Some(Named::Home)),
+        KC_N8 => (Code::Numpad8, Some(Named::ArrowUp)),
+        KC_N9 => (Code::Numpad9, Some(Named::PageUp)),
+        KC_N4 => (Code::Numpad4, Some(Named::ArrowLeft)),
+        KC_N5 => (Code::Numpad5, None),
+        KC_N6 => (Code::Numpad6, Some(Named::ArrowRight)),
+        KC_N1 => (Code::Numpad1, Some(Named::End)),
+        KC_N2 => (Code::Numpad2, Some(Named::ArrowDown)),
+        KC_N3 => (Code::Numpad3, Some(Named::PageDown)),
+        KC_N0 => (Code::Numpad0, Some(Named::Insert)),
+        KC_NPERIOD => (Code::NumpadDecimal, Some(Named::Delete)),
+        _ => return (None, None),
+    };
+    (Some(code), named)
+}
+
+/// The character typed by the key on a US keyboard without modifiers
+fn us_char(keycode: u32) -> Option<char> {
+    let nth = |chars: &str, first: u32| chars.chars().nth((keycode - first) as usize);
+    match keycode {
+        KC_1..=KC_0 => nth("1234567890", KC_1),
+        KC_Q..=KC_P => nth("qwertyuiop", KC_Q),
+        KC_A..=KC_L => nth("asdfghjkl", KC_A),
+        KC_Z..=KC_M => nth("zxcvbnm", KC_Z),
+        KC_BACKTICK => Some('`'),
+        KC_MINUS => Some('-'),
+        KC_EQUALS => Some('='),
+        KC_LBRACKET => Some('['),
+        KC_RBRACKET => Some(']'),
+        KC_SEMICOLON => Some(';'),
+        KC_QUOTE => Some('\''),
+        KC_BACKSLASH => Some('\\'),
+        KC_COMMA => Some(','),
+        KC_PERIOD => Some('.'),
+        KC_SLASH => Some('/'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KC_S: u32 = KC_A + 1;
+
+    fn press(keycode: u32, mods: u32, c: char) -> keyboard::Event {
+        key_event(true, keycode, mods, c as u32)
+    }
+
+    #[test]
+    fn named_keys() {
+        let keyboard::Event::KeyPressed {
+            key,
+            modified_key,
+            physical_key,
+            text,
+            ..
+        } = press(KC_SPACE, 0, ' ')
+        else {
+            unreachable!();
+        };
+        assert_eq!(key, Key::Named(Named::Space));
+        assert_eq!(modified_key, Key::Named(Named::Space));
+        assert_eq!(physical_key, Physical::Code(Code::Space));
+        assert_eq!(text.as_deref(), Some(" "));
+
+        for (keycode, named) in [
+            (KC_LEFT, Named::ArrowLeft),
+            (KC_ESCAPE, Named::Escape),
+            (KC_F1, Named::F1),
+            (KC_F12, Named::F12),
+            (KC_PRTSCR, Named::PrintScreen),
+        ] {
+            let keyboard::Event::KeyPressed { key, text, .. } = press(keycode, 0, '\0') else {
+                unreachable!();
+            };
+            assert_eq!(key, Key::Named(named));
+            assert_eq!(text, None);
+        }
+    }
+
+    #[test]
+    fn characters_and_modifiers() {
+        let keyboard::Event::KeyPressed {
+            key,
+            modified_key,
+            physical_key,
+            modifiers,
+            text,
+            ..
+        } = press(KC_S, KM_LSHIFT | KM_RCTRL, 'S')
+        else {
+            unreachable!();
+        };
+        assert_eq!(key, Key::Character("s".into()));
+        assert_eq!(modified_key, Key::Character("S".into()));
+        assert_eq!(physical_key, Physical::Code(Code::KeyS));
+        assert_eq!(modifiers, Modifiers::SHIFT | Modifiers::CTRL);
+        assert_eq!(text.as_deref(), Some("S"));
+
+        let keyboard::Event::KeyReleased {
+            key,
+            location,
+            modifiers,
+        } = key_event(false, KC_RALT, KM_RALT, 0)
+        else {
+            unreachable!();
+        };
+        assert_eq!(key, Key::Named(Named::Alt));
+        assert_eq!(location, Location::Right);
+        assert_eq!(modifiers, Modifiers::ALT);
+    }
+
+    #[test]
+    fn numeric_block_follows_num_lock() {
+        let keyboard::Event::KeyPressed { key, location, .. } = press(KC_N8, 0, '8') else {
+            unreachable!();
+        };
+        assert_eq!(key, Key::Character("8".into()));
+        assert_eq!(location, Location::Numpad);
+        let keyboard::Event::KeyPressed { key, .. } = press(KC_N8, 0, '\0') else {
+            unreachable!();
+        };
+        assert_eq!(key, Key::Named(Named::ArrowUp));
+    }
+
+    #[test]
+    fn triggers_shortcuts() {
+        use crate::shortcut::{Binding, Shortcut, find};
+        use iced_widget::core::Event;
+
+        let bindings = || {
+            vec![
+                Binding::new(Shortcut::named(Named::Space), "Pause", 1),
+                Binding::new(Shortcut::named(Named::ArrowLeft), "Back", 2),
+                Binding::new(Shortcut::named(Named::Escape), "Quit", 3),
+                Binding::new(Shortcut::char('s').with(Modifiers::CTRL), "Save", 4),
+                Binding::new(Shortcut::char('+'), "Zoom in", 5),
+            ]
+        };
+        let find = |event| find(bindings(), &Event::Keyboard(event), false);
+        assert_eq!(find(press(KC_SPACE, 0, ' ')), Some(1));
+        assert_eq!(find(press(KC_LEFT, 0, '\0')), Some(2));
+        assert_eq!(find(press(KC_ESCAPE, 0, '\0')), Some(3));
+        // the layouts type nothing with Ctrl
+        assert_eq!(find(press(KC_S, KM_LCTRL, '\0')), Some(4));
+        assert_eq!(find(press(KC_S, KM_LCTRL | KM_LSHIFT, '\0')), Some(4));
+        assert_eq!(find(press(KC_S, 0, 's')), None);
+        assert_eq!(find(press(KC_EQUALS, KM_LSHIFT, '+')), Some(5));
+    }
+}
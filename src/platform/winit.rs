@@ -1,87 +1,183 @@
 use std::sync::Arc;
+use std::time::Instant;
 
+use arboard::{GetExtLinux, LinuxClipboardKind, SetExtLinux};
+use iced_widget::core::clipboard::Kind;
 use iced_widget::core::mouse::{self, Cursor, Interaction};
-use iced_widget::core::{Event, Point, keyboard};
+use iced_widget::core::{Event, Point, Rectangle, Size, keyboard, touch, window};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
-use winit::event_loop::{ActiveEventLoop, EventLoop};
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::{Ime, TouchPhase, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::ModifiersState;
 use winit::window::WindowId;
 
+mod conversion;
+
 pub use winit::window::Window;
 
-use crate::{AppInner, ProgramExt, SendMsgFn, WindowOptions};
+use crate::thread::Supervisor;
+use crate::{AppInner, Error, ProgramExt, SendMsgFn, WindowOptions, channel, clipboard, warn};
+
+type ProgramChannel<M> = (channel::Sender<M>, channel::Receiver<M>);
+
+/// Wakes the event loop up to drain the channel of the program
+#[derive(Debug)]
+pub struct Wake;
 
 pub struct App<T: ProgramExt> {
     inner: Option<AppInner<T>>,
     cursor: Cursor,
-    // app that we will run - we store it here until the first Resume event
-    prepared_app: Option<(T, WindowOptions)>,
+    modifiers: ModifiersState,
+    // app that we will run, with its channel and the supervisor of the threads it started - we
+    // store it here until the first Resume event
+    prepared_app: Option<(T, WindowOptions, ProgramChannel<T::Message>, Supervisor)>,
+    // why the window couldn't be opened, returned from `run` once the event loop exits
+    error: Option<Error>,
 }
 
-impl<T: ProgramExt> ApplicationHandler<T::Message> for App<T> {
+impl<T: ProgramExt> App<T> {
+    /// Stops the program once: it is dropped, so that the events still arriving before the
+    /// event loop exits, like a queued `Exit` after the window was closed, are ignored
+    fn exit(&mut self, el: &ActiveEventLoop) {
+        el.exit();
+        if let Some(mut inner) = self.inner.take() {
+            inner.stop();
+        }
+    }
+
+    fn create_inner(
+        &self,
+        el: &ActiveEventLoop,
+        app: T,
+        options: &WindowOptions,
+        channel: ProgramChannel<T::Message>,
+        supervisor: Supervisor,
+    ) -> Result<AppInner<T>, Error> {
+        let mut attributes = Window::default_attributes()
+            .with_title(options.caption.clone())
+            .with_resizable(options.resizable)
+            .with_decorations(options.decorations)
+            .with_maximized(options.maximized)
+            .with_fullscreen(
+                options
+                    .fullscreen
+                    .then_some(winit::window::Fullscreen::Borderless(None)),
+            )
+            // shown after the first frame is drawn, to avoid a flash of
+            // an empty window with the default background
+            .with_visible(false);
+        if let Some(size) = options.size {
+            attributes = attributes.with_inner_size(winit_size(size, options.scale));
+        }
+        if let Some(position) = options.position {
+            attributes = attributes.with_position(winit_position(position, options.scale));
+        }
+        if let Some(size) = options.min_size {
+            attributes = attributes.with_min_inner_size(winit_size(size, options.scale));
+        }
+        if let Some(size) = options.max_size {
+            attributes = attributes.with_max_inner_size(winit_size(size, options.scale));
+        }
+        let window = el
+            .create_window(attributes)
+            .map_err(|e| Error::Window(e.to_string()))?;
+        AppInner::new(Arc::new(window), app, options, channel, supervisor)
+    }
+}
+
+impl<T: ProgramExt> ApplicationHandler<Wake> for App<T> {
     fn resumed(&mut self, el: &ActiveEventLoop) {
-        if let Some((app, options)) = self.prepared_app.take() {
-            self.inner = Some(AppInner::new(
-                Arc::new(
-                    el.create_window(
-                        Window::default_attributes()
-                            .with_title(options.caption)
-                            .with_maximized(options.maximized),
-                    )
-                    .unwrap(),
-                ),
-                app,
-            ));
+        if let Some((app, options, channel, supervisor)) = self.prepared_app.take() {
+            match self.create_inner(el, app, &options, channel, supervisor) {
+                Ok(inner) => self.inner = Some(inner),
+                Err(e) => {
+                    self.error = Some(e);
+                    el.exit();
+                    return;
+                }
+            }
+        } else if let Some(inner) = self.inner.as_mut() {
+            inner.resume();
         }
-        self.inner.as_mut().unwrap().update(self.cursor);
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        inner.update(self.cursor);
+        inner.target.w.set_visible(true);
     }
 
-    fn user_event(&mut self, _el: &ActiveEventLoop, msg: T::Message) {
+    fn suspended(&mut self, _el: &ActiveEventLoop) {
         if let Some(inner) = self.inner.as_mut() {
-            inner.program.queue_message(msg);
-            inner.w.request_redraw();
+            inner.suspend();
         }
     }
 
+    fn user_event(&mut self, el: &ActiveEventLoop, _: Wake) {
+        if let Some(inner) = self.inner.as_mut() {
+            if inner.drain_actions().is_break() {
+                self.exit(el);
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, el: &ActiveEventLoop) {
+        let Some(inner) = self.inner.as_ref() else {
+            return;
+        };
+        el.set_control_flow(match inner.next_redraw() {
+            Some(at) if at <= Instant::now() => {
+                inner.target.w.request_redraw();
+                ControlFlow::Wait
+            }
+            Some(at) => ControlFlow::WaitUntil(at),
+            None => ControlFlow::Wait,
+        });
+    }
+
     fn window_event(&mut self, el: &ActiveEventLoop, _wid: WindowId, event: WindowEvent) {
+        if self.inner.is_none() {
+            return;
+        }
         let new_ev = match event {
             WindowEvent::CursorLeft { .. } => {
                 self.cursor = Cursor::Unavailable;
                 Event::Mouse(mouse::Event::CursorLeft)
             }
             WindowEvent::CursorMoved { position, .. } => {
-                let p = Point::new(position.x as f32, position.y as f32);
+                let p = self
+                    .inner
+                    .as_ref()
+                    .unwrap()
+                    .to_logical(Point::new(position.x as f32, position.y as f32));
                 self.cursor = Cursor::Available(p);
                 Event::Mouse(mouse::Event::CursorMoved { position: p })
             }
             WindowEvent::CloseRequested => {
-                el.exit();
-                self.inner.as_mut().unwrap().program.program().stop();
+                if self.inner.as_mut().unwrap().close_requested() {
+                    self.exit(el);
+                }
+                return;
+            }
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                // the new size is picked up by the next frame, which also tells the program
+                self.inner.as_ref().unwrap().target.w.request_redraw();
                 return;
             }
+            WindowEvent::Focused(true) => Event::Window(window::Event::Focused),
+            WindowEvent::Focused(false) => Event::Window(window::Event::Unfocused),
             WindowEvent::RedrawRequested => {
                 self.inner.as_mut().unwrap().update(self.cursor);
                 return;
             }
             WindowEvent::KeyboardInput { event, .. } => {
-                let ch = keyboard::Key::Character(event.text.clone().unwrap_or_default());
-                Event::Keyboard(match event.state {
-                    winit::event::ElementState::Pressed => keyboard::Event::KeyPressed {
-                        key: ch.clone(),
-                        modified_key: ch,
-                        physical_key: keyboard::key::Physical::Unidentified(
-                            keyboard::key::NativeCode::Unidentified,
-                        ),
-                        location: keyboard::Location::Standard,
-                        modifiers: keyboard::Modifiers::default(),
-                        text: event.text,
-                    },
-                    winit::event::ElementState::Released => keyboard::Event::KeyReleased {
-                        key: ch,
-                        location: keyboard::Location::Standard,
-                        modifiers: keyboard::Modifiers::default(),
-                    },
-                })
+                Event::Keyboard(conversion::key_event(event, self.modifiers))
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                Event::Keyboard(keyboard::Event::ModifiersChanged(conversion::modifiers(
+                    self.modifiers,
+                )))
             }
             WindowEvent::MouseWheel { delta, .. } => Event::Mouse(mouse::Event::WheelScrolled {
                 delta: match delta {
@@ -90,9 +186,13 @@ impl<T: ProgramExt> ApplicationHandler<T::Message> for App<T> {
                         y: y as f32,
                     },
                     winit::event::MouseScrollDelta::PixelDelta(physical_position) => {
+                        let delta = self.inner.as_ref().unwrap().to_logical(Point::new(
+                            physical_position.x as f32,
+                            physical_position.y as f32,
+                        ));
                         mouse::ScrollDelta::Pixels {
-                            x: physical_position.x as f32,
-                            y: physical_position.y as f32,
+                            x: delta.x,
+                            y: delta.y,
                         }
                     }
                 },
@@ -109,45 +209,203 @@ impl<T: ProgramExt> ApplicationHandler<T::Message> for App<T> {
                     winit::event::ElementState::Released => mouse::Event::ButtonReleased(button),
                 })
             }
+            WindowEvent::Ime(Ime::Preedit(text, _)) => {
+                self.inner.as_mut().unwrap().set_preedit(text);
+                return;
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                self.inner.as_mut().unwrap().commit_text(&text);
+                return;
+            }
+            WindowEvent::Ime(Ime::Disabled) => {
+                self.inner.as_mut().unwrap().set_preedit(String::new());
+                return;
+            }
+            WindowEvent::Touch(winit::event::Touch {
+                phase,
+                location,
+                id,
+                ..
+            }) => {
+                let position = self
+                    .inner
+                    .as_ref()
+                    .unwrap()
+                    .to_logical(Point::new(location.x as f32, location.y as f32));
+                let id = touch::Finger(id);
+                // like in iced_winit, widgets see the last touched point as the cursor
+                self.cursor = Cursor::Available(position);
+                Event::Touch(match phase {
+                    TouchPhase::Started => touch::Event::FingerPressed { id, position },
+                    TouchPhase::Moved => touch::Event::FingerMoved { id, position },
+                    TouchPhase::Ended => touch::Event::FingerLifted { id, position },
+                    TouchPhase::Cancelled => touch::Event::FingerLost { id, position },
+                })
+            }
             _ => {
                 return;
             }
         };
-        let inner = self.inner.as_mut().unwrap();
-        inner.program.queue_event(new_ev);
-        inner.w.request_redraw();
+        // the redraw is scheduled once all pending events are queued, see `about_to_wait`
+        self.inner.as_mut().unwrap().queue_event(new_ev);
+    }
+}
+
+/// Size in logical pixels, scaled by the overridden scale factor if there is one, otherwise by
+/// the one of the monitor
+fn winit_size(size: Size, scale: Option<f64>) -> winit::dpi::Size {
+    match scale {
+        Some(scale) => {
+            let size = crate::window::to_physical(size, scale);
+            PhysicalSize::new(size.width, size.height).into()
+        }
+        None => LogicalSize::new(size.width, size.height).into(),
+    }
+}
+
+fn winit_position(position: Point, scale: Option<f64>) -> winit::dpi::Position {
+    match scale {
+        Some(scale) => PhysicalPosition::new(
+            (position.x as f64 * scale).round() as i32,
+            (position.y as f64 * scale).round() as i32,
+        )
+        .into(),
+        None => LogicalPosition::new(position.x, position.y).into(),
+    }
+}
+
+pub fn change_window(w: &Window, request: crate::window::Request, scale_factor: f64) {
+    use crate::window::{Mode, Request};
+
+    let scale = Some(scale_factor);
+    match request {
+        Request::Resize(size) => {
+            // the new size is reported by a Resized event if the request succeeds
+            let _ = w.request_inner_size(winit_size(size, scale));
+        }
+        Request::Move(position) => w.set_outer_position(winit_position(position, scale)),
+        Request::MinSize(size) => w.set_min_inner_size(size.map(|s| winit_size(s, scale))),
+        Request::MaxSize(size) => w.set_max_inner_size(size.map(|s| winit_size(s, scale))),
+        Request::Resizable(resizable) => w.set_resizable(resizable),
+        Request::Decorations(decorations) => w.set_decorations(decorations),
+        Request::ToggleDecorations => w.set_decorations(!w.is_decorated()),
+        Request::Mode(Mode::Fullscreen) => {
+            w.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+            w.set_visible(true);
+        }
+        Request::Mode(Mode::Windowed) => {
+            w.set_fullscreen(None);
+            w.set_visible(true);
+        }
+        Request::Mode(Mode::Hidden) => w.set_visible(false),
+        Request::Maximize(maximized) => w.set_maximized(maximized),
+        Request::ToggleMaximize => w.set_maximized(!w.is_maximized()),
+        Request::Minimize(minimized) => w.set_minimized(minimized),
+        Request::CursorVisible(visible) => w.set_cursor_visible(visible),
     }
 }
 
 pub fn set_cursor(w: &Window, interaction: Interaction) {
+    use winit::window::CursorIcon;
+
     w.set_cursor(winit::window::Cursor::Icon(match interaction {
-        iced_widget::core::mouse::Interaction::Pointer => winit::window::CursorIcon::Pointer,
-        iced_widget::core::mouse::Interaction::Grab => winit::window::CursorIcon::Grabbing,
-        _ => winit::window::CursorIcon::Default,
+        Interaction::None | Interaction::Idle => CursorIcon::Default,
+        Interaction::Pointer => CursorIcon::Pointer,
+        Interaction::Grab => CursorIcon::Grab,
+        Interaction::Grabbing => CursorIcon::Grabbing,
+        Interaction::Text => CursorIcon::Text,
+        Interaction::Crosshair => CursorIcon::Crosshair,
+        Interaction::Working => CursorIcon::Progress,
+        Interaction::ResizingHorizontally => CursorIcon::EwResize,
+        Interaction::ResizingVertically => CursorIcon::NsResize,
+        Interaction::ResizingDiagonallyUp => CursorIcon::NeswResize,
+        Interaction::ResizingDiagonallyDown => CursorIcon::NwseResize,
+        Interaction::NotAllowed => CursorIcon::NotAllowed,
+        Interaction::ZoomIn => CursorIcon::ZoomIn,
+        Interaction::ZoomOut => CursorIcon::ZoomOut,
+        Interaction::Cell => CursorIcon::Cell,
+        Interaction::Move => CursorIcon::Move,
+        Interaction::Copy => CursorIcon::Copy,
+        Interaction::Help => CursorIcon::Help,
     }));
 }
 
+pub fn set_ime_area(w: &Window, area: Option<Rectangle>) {
+    w.set_ime_allowed(area.is_some());
+    if let Some(area) = area {
+        w.set_ime_cursor_area(
+            PhysicalPosition::new(area.x, area.y),
+            PhysicalSize::new(area.width, area.height),
+        );
+    }
+}
+
+/// Clipboard of the X11 server
+struct SystemClipboard(arboard::Clipboard);
+
+impl clipboard::System for SystemClipboard {
+    fn read(&mut self, kind: Kind) -> Result<Option<String>, String> {
+        let get = self.0.get();
+        let get = match kind {
+            Kind::Standard => get,
+            Kind::Primary => get.clipboard(LinuxClipboardKind::Primary),
+        };
+        match get.text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn write(&mut self, kind: Kind, contents: &str) -> Result<(), String> {
+        let set = self.0.set();
+        let set = match kind {
+            Kind::Standard => set,
+            Kind::Primary => set.clipboard(LinuxClipboardKind::Primary),
+        };
+        set.text(contents).map_err(|e| e.to_string())
+    }
+}
+
+pub fn system_clipboard() -> Option<Box<dyn clipboard::System>> {
+    match arboard::Clipboard::new() {
+        Ok(c) => Some(Box::new(SystemClipboard(c))),
+        Err(e) => {
+            warn!("Error opening clipboard, using an in-process one: {e}");
+            None
+        }
+    }
+}
+
 pub fn run<T: ProgramExt>(
     create_app: impl FnOnce(&(dyn (Fn() -> SendMsgFn<T::Message>) + Send + 'static)) -> T + 'static,
     options: WindowOptions,
-) {
-    let el = EventLoop::with_user_event().build().unwrap();
+) -> Result<(), Error> {
+    let el = EventLoop::with_user_event()
+        .build()
+        .map_err(|e| Error::Display(e.to_string()))?;
     let proxy = el.create_proxy();
+    let (sender, receiver) = channel::channel(move || {
+        // fails only once the event loop exited, and then there is nothing to wake
+        let _ = proxy.send_event(Wake);
+    });
+    let supervisor = Supervisor::default();
+    let program = {
+        let _entered = supervisor.enter();
+        create_app(&{
+            let sender = sender.clone();
+            move || sender.send_msg_fn()
+        })
+    };
 
-    el.run_app(&mut App {
+    let mut app = App {
         inner: None,
         cursor: Cursor::Unavailable,
-        prepared_app: Some((
-            create_app(&move || {
-                let proxy = proxy.clone();
-                Box::new(move |msg: T::Message| {
-                    if let Err(e) = proxy.send_event(msg) {
-                        eprintln!("Error sending event: {}", e);
-                    }
-                })
-            }),
-            options,
-        )),
-    })
-    .unwrap();
+        modifiers: ModifiersState::default(),
+        prepared_app: Some((program, options, (sender, receiver), supervisor)),
+        error: None,
+    };
+    el.run_app(&mut app)
+        .map_err(|e| Error::Display(e.to_string()))?;
+    app.error.map_or(Ok(()), Err)
 }
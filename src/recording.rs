@@ -0,0 +1,234 @@
+//! Recording the input of a program and replaying it later, e.g. to reproduce a bug reported
+//! by a user.
+//!
+//! A recording is a headless script (see [`crate::headless::parse_script`]) with a timestamp on
+//! every line. It contains all the input events and the frames in which they were processed.
+//! Messages sent to the program by tasks and other threads are written as comments: they are
+//! produced again by the program itself during the replay.
+//!
+//! The replay is therefore deterministic only as far as those messages are. Results of
+//! [`worker`]s, ticks of [`time::every`] and messages of other threads come whenever they are
+//! ready, which can be in different frames than in the recording, or not at all if they
+//! depend on the outside world (e.g. a file which changed meanwhile). The comments help to find
+//! out where a replay diverged.
+//!
+//! A recording is replayed either in a window, with [`REPLAY_ENV`] or [`WindowOptions::replay`],
+//! or without one, by running it as a script with [`crate::headless::SCRIPT_ENV`].
+//!
+//! [`WindowOptions::replay`]: crate::WindowOptions::replay
+//! [`worker`]: crate::worker
+//! [`time::every`]: crate::time::every
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use iced_widget::core::mouse::{self, Cursor};
+use iced_widget::core::{Event, Size, touch};
+
+use crate::Error;
+use crate::headless::{self, Step};
+
+/// Environment variable with a path to record the input into, like [`crate::WindowOptions::record`]
+pub const RECORD_ENV: &str = "HELENOS_ICED_RECORD";
+/// Environment variable with a path of a recording to replay in the window, like
+/// [`crate::WindowOptions::replay`]
+pub const REPLAY_ENV: &str = "HELENOS_ICED_REPLAY";
+
+// debug output of large messages (e.g. with image data) is cut to this many characters
+const MAX_MESSAGE_LENGTH: usize = 200;
+
+pub(crate) struct Recorder {
+    file: LineWriter<File>,
+    start: Instant,
+    // something was recorded since the last frame
+    pending: bool,
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Recorder {
+    pub(crate) fn new(path: &Path) -> io::Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "# Replay in a window with {REPLAY_ENV}={0}, or headless with {1}={0}",
+            path.display(),
+            headless::SCRIPT_ENV
+        )?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+            pending: false,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        // lines are flushed right away, so that the recording survives a crash
+        writeln!(self.file, "@{} {line}", self.start.elapsed().as_millis())?;
+        self.pending = true;
+        Ok(())
+    }
+
+    pub(crate) fn event(&mut self, event: &Event) -> io::Result<()> {
+        match headless::format_event(event) {
+            Some(line) => self.write(&line),
+            None => self.write(&format!("# unsupported event: {event:?}")),
+        }
+    }
+
+    pub(crate) fn message(&mut self, message: &dyn Debug) -> io::Result<()> {
+        let mut text = format!("{message:?}").replace('\n', " ");
+        if let Some((end, _)) = text.char_indices().nth(MAX_MESSAGE_LENGTH) {
+            text.truncate(end);
+            text += "...";
+        }
+        self.write(&format!("# message: {text}"))
+    }
+
+    pub(crate) fn resize(&mut self, size: Size<u32>) -> io::Result<()> {
+        self.write(&format!("resize {} {}", size.width, size.height))
+    }
+
+    /// Marks the end of a frame which processed the things recorded since the previous one
+    pub(crate) fn frame(&mut self) -> io::Result<()> {
+        if self.pending {
+            self.write("redraw")?;
+            self.pending = false;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Replay<M> {
+    start: Instant,
+    // steps with their time since the start
+    steps: VecDeque<(Duration, Step<M>)>,
+    cursor: Cursor,
+}
+
+impl<M> Replay<M> {
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|e| Error::Script(format!("reading {}: {e}", path.display())))?;
+        Self::parse(&src).map_err(Error::Script)
+    }
+
+    /// Parses a recording, which starts to play right away
+    fn parse(src: &str) -> Result<Self, String> {
+        let mut at = Duration::ZERO;
+        let steps = headless::parse_script(src)?
+            .into_iter()
+            .filter_map(|step| match step {
+                Step::Wait(duration) => {
+                    at += duration;
+                    None
+                }
+                step => Some((at, step)),
+            })
+            .collect();
+        Ok(Self {
+            start: Instant::now(),
+            steps,
+            cursor: Cursor::Unavailable,
+        })
+    }
+
+    /// Position of the replayed cursor, which is used instead of the real one
+    pub(crate) fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.steps.front().map(|(at, _)| self.start + *at)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Takes the steps that are due, up to the end of the first recorded frame among them, so
+    /// that the events are processed in the same frames as when they were recorded
+    pub(crate) fn due_steps(&mut self, now: Instant) -> Vec<Step<M>> {
+        let mut due = Vec::new();
+        while let Some((at, _)) = self.steps.front() {
+            if self.start + *at > now {
+                break;
+            }
+            let (_, step) = self.steps.pop_front().unwrap();
+            match &step {
+                Step::Event(Event::Mouse(mouse::Event::CursorMoved { position })) => {
+                    self.cursor = Cursor::Available(*position);
+                }
+                Step::Event(Event::Mouse(mouse::Event::CursorLeft)) => {
+                    self.cursor = Cursor::Unavailable;
+                }
+                Step::Event(Event::Touch(
+                    touch::Event::FingerPressed { position, .. }
+                    | touch::Event::FingerMoved { position, .. },
+                )) => {
+                    self.cursor = Cursor::Available(*position);
+                }
+                _ => {}
+            }
+            let end_of_frame = matches!(step, Step::Redraw);
+            due.push(step);
+            if end_of_frame {
+                break;
+            }
+        }
+        due
+    }
+}
+
+/// Whether the event comes from the user, and so is ignored while a recording is replayed
+pub(crate) fn is_input(event: &Event) -> bool {
+    !matches!(event, Event::Window(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use iced_widget::core::Point;
+
+    use super::*;
+
+    #[test]
+    fn due_steps_stop_at_redraw() {
+        let mut replay =
+            Replay::<()>::parse("move 1 2\nredraw\nfocus\nredraw\n@50 leave\nredraw\n@100 unfocus")
+                .unwrap();
+        let start = replay.start;
+        let names = |steps: Vec<Step<()>>| -> Vec<String> {
+            steps
+                .iter()
+                .map(|step| match step {
+                    Step::Event(event) => headless::format_event(event).unwrap(),
+                    Step::Redraw => "redraw".to_owned(),
+                    other => panic!("unexpected step: {other:?}"),
+                })
+                .collect()
+        };
+
+        assert_eq!(names(replay.due_steps(start)), ["move 1 2", "redraw"]);
+        assert_eq!(replay.cursor(), Cursor::Available(Point::new(1.0, 2.0)));
+        assert_eq!(names(replay.due_steps(start)), ["focus", "redraw"]);
+        assert!(replay.due_steps(start).is_empty());
+        assert_eq!(replay.next_due(), Some(start + Duration::from_millis(50)));
+
+        let later = start + Duration::from_millis(200);
+        assert_eq!(names(replay.due_steps(later)), ["leave", "redraw"]);
+        assert_eq!(replay.cursor(), Cursor::Unavailable);
+        assert_eq!(names(replay.due_steps(later)), ["unfocus"]);
+        assert!(replay.is_finished());
+    }
+}
@@ -0,0 +1,55 @@
+//! Scheduling of redraws, so that the backends only draw when something changed or widgets
+//! requested an animation frame, and otherwise sleep.
+
+#[cfg(target_os = "helenos")]
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use iced_widget::core::window::RedrawRequest;
+
+/// Shortest time between two frames requested by animations, there is no vsync to limit them
+const FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+/// Time at which a frame should be drawn to satisfy the request of the widgets
+pub(crate) fn requested_frame(last_frame: Instant, request: RedrawRequest) -> Instant {
+    match request {
+        RedrawRequest::NextFrame => last_frame + FRAME_INTERVAL,
+        RedrawRequest::At(at) => at,
+    }
+}
+
+/// Wakes up a backend sleeping until the next scheduled frame, when an event or message arrives
+/// from another thread. Winit has its own event loop proxy for that.
+#[cfg(target_os = "helenos")]
+#[derive(Debug, Default)]
+pub(crate) struct Wakeup {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+#[cfg(target_os = "helenos")]
+impl Wakeup {
+    pub(crate) fn wake(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+
+    /// Blocks until [`Self::wake`] is called or the deadline passes. Returns immediately if it
+    /// was woken since the last wait.
+    pub(crate) fn wait_until(&self, deadline: Option<Instant>) {
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            match deadline {
+                None => woken = self.condvar.wait(woken).unwrap(),
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        break;
+                    }
+                    woken = self.condvar.wait_timeout(woken, timeout).unwrap().0;
+                }
+            }
+        }
+        *woken = false;
+    }
+}
@@ -0,0 +1,105 @@
+//! Capturing the contents of the window, or rendering any element offscreen, into an image.
+//!
+//! The window can also be captured by pressing Print Screen, which saves the frame as PNG into
+//! the current directory.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use iced_runtime::user_interface::{self, UserInterface};
+use iced_runtime::{Task, window};
+use iced_widget::Theme;
+use iced_widget::core::mouse::Cursor;
+use iced_widget::core::renderer::Style;
+use iced_widget::core::{Event, Size, keyboard};
+use iced_widget::graphics::Viewport;
+pub use image::RgbaImage;
+
+use crate::platform::headless::Offscreen;
+use crate::{Appearance, Element, Target, WindowOptions, create_renderer, error, info};
+
+/// Captures the last frame drawn in the window of the program
+pub fn capture() -> Task<RgbaImage> {
+    window::get_latest()
+        .and_then(window::screenshot)
+        .map(|screenshot| {
+            RgbaImage::from_raw(
+                screenshot.size.width,
+                screenshot.size.height,
+                screenshot.bytes.to_vec(),
+            )
+            .expect("Screenshot has RGBA pixels of its size")
+        })
+}
+
+/// Renders the element offscreen, with the given logical size and theme.
+///
+/// The fonts, the default font and the text size are taken from the options, which should be
+/// the ones the program runs with, so that the text looks like in its window.
+pub fn render<'a, M>(
+    element: impl Into<Element<'a, M>>,
+    size: Size,
+    theme: &Theme,
+    options: &WindowOptions,
+) -> RgbaImage {
+    let mut renderer = create_renderer(options);
+    let appearance = Appearance::of(theme);
+
+    let mut user_interface = UserInterface::build(
+        element,
+        size,
+        user_interface::Cache::default(),
+        &mut renderer,
+    );
+    user_interface.draw(
+        &mut renderer,
+        theme,
+        &Style {
+            text_color: appearance.text_color,
+        },
+        Cursor::Unavailable,
+    );
+
+    let viewport = Viewport::with_physical_size(
+        Size::new(size.width.ceil() as u32, size.height.ceil() as u32),
+        1.0,
+    );
+    render_frame(&mut renderer, &viewport, appearance.background_color)
+}
+
+/// Draws what is currently in the renderer into an image
+pub(crate) fn render_frame(
+    renderer: &mut iced_widget::Renderer,
+    viewport: &Viewport,
+    background: iced_widget::core::Color,
+) -> RgbaImage {
+    let size = viewport.physical_size();
+    let mut offscreen = Offscreen::new(size);
+    offscreen
+        .present(renderer, viewport, background)
+        .expect("Drawing offscreen can't fail");
+    offscreen.to_image()
+}
+
+pub(crate) fn is_hotkey(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            key: keyboard::Key::Named(keyboard::key::Named::PrintScreen),
+            ..
+        })
+    )
+}
+
+/// Saves the image as PNG into the current directory, named after the current time
+pub(crate) fn save(image: &RgbaImage) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = PathBuf::from(format!("screenshot-{timestamp}.png"));
+    match image.save(&path) {
+        Ok(()) => info!("Screenshot saved to {}", path.display()),
+        Err(e) => error!("Error saving screenshot {}: {e}", path.display()),
+    }
+}
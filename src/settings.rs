@@ -0,0 +1,76 @@
+//! Settings of a program remembered between runs, see [`crate::ProgramExt::Settings`].
+//!
+//! They are stored as TOML in `<config dir>/helenos-iced/<executable name>.toml`, where the
+//! config directory is `$XDG_CONFIG_HOME` or `~/.config`, and `/w/cfg` on HelenOS.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::{error, warn};
+
+/// Path of the settings file of the running executable
+pub fn path() -> Option<PathBuf> {
+    let exe = PathBuf::from(std::env::args_os().next()?);
+    let name = exe.file_stem()?;
+    let mut path = config_dir()?.join("helenos-iced").join(name);
+    path.set_extension("toml");
+    Some(path)
+}
+
+#[cfg(target_os = "helenos")]
+fn config_dir() -> Option<PathBuf> {
+    // the writable part of the filesystem
+    Some(PathBuf::from("/w/cfg"))
+}
+
+#[cfg(not(target_os = "helenos"))]
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))
+}
+
+/// Loads the saved settings. Missing or invalid ones are replaced by the defaults.
+pub(crate) fn load<S: DeserializeOwned + Default>() -> S {
+    let Some(path) = path() else {
+        return S::default();
+    };
+    let src = match std::fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return S::default(),
+        Err(e) => {
+            error!("Error reading settings {}: {e}", path.display());
+            return S::default();
+        }
+    };
+    toml::from_str(&src).unwrap_or_else(|e| {
+        warn!(
+            "Error parsing settings {}, using defaults: {e}",
+            path.display()
+        );
+        S::default()
+    })
+}
+
+pub(crate) fn save<S: Serialize>(settings: &S) {
+    let Some(path) = path() else {
+        error!("Error saving settings: no config directory");
+        return;
+    };
+    let result = toml::to_string(settings)
+        .map_err(|e| e.to_string())
+        .and_then(|src| {
+            // written next to the file and renamed, so that a crash doesn't leave it half-written
+            let tmp = path.with_extension("toml.tmp");
+            std::fs::create_dir_all(path.parent().unwrap())
+                .and_then(|()| std::fs::write(&tmp, src))
+                .and_then(|()| std::fs::rename(&tmp, &path))
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        error!("Error saving settings {}: {e}", path.display());
+    }
+}
@@ -0,0 +1,356 @@
+//! Keyboard shortcuts of the program, declared by [`ProgramExt::shortcuts`] as bindings of key
+//! combinations to messages.
+//!
+//! A key press matching a binding sends its message and is not passed to the widgets. Shortcuts
+//! without Ctrl, Alt or Logo are not used while a text input is focused, so that they can
+//! still be typed. When several bindings have the same shortcut, the first one is used and the
+//! conflict is logged, as well as shortcuts taken by the library itself.
+//!
+//! F1 shows an overlay listing all the shortcuts with their descriptions.
+//!
+//! [`ProgramExt::shortcuts`]: crate::ProgramExt::shortcuts
+
+use std::borrow::Cow;
+use std::fmt;
+
+use iced_widget::Renderer;
+use iced_widget::core::keyboard::key::Named;
+use iced_widget::core::keyboard::{self, Key, Modifiers};
+use iced_widget::core::text::Shaping;
+use iced_widget::core::{Color, Event, Font, Point, Rectangle, Size};
+
+use crate::panel;
+
+const STYLE: panel::Style = panel::Style {
+    background: Color::from_rgba(0.0, 0.0, 0.0, 0.8),
+    border_color: Color::TRANSPARENT,
+    border_width: 0.0,
+    text_color: Color::WHITE,
+    font: Font::with_name("Noto Sans Mono"),
+    text_size: 14.0,
+    line_height: 20.0,
+    padding: 10.0,
+    shaping: Shaping::Basic,
+};
+// width of the column with the shortcuts, in characters of the monospace font
+const SHORTCUT_COLUMN: usize = 16;
+
+/// Keys used by the library, with what they do
+const RESERVED: &[(Named, &str)] = &[
+    (Named::F1, "Show keyboard shortcuts"),
+    (Named::F12, "Show debug overlay"),
+    (Named::PrintScreen, "Save screenshot"),
+];
+
+/// A key pressed together with modifiers
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    key: Key,
+    modifiers: Modifiers,
+}
+
+impl Shortcut {
+    /// A named key like [`Named::Space`] or [`Named::ArrowLeft`], without modifiers
+    pub fn named(key: Named) -> Self {
+        Shortcut {
+            key: Key::Named(key),
+            modifiers: Modifiers::empty(),
+        }
+    }
+
+    /// A key typing the character, without modifiers. The case of letters doesn't matter, and
+    /// Shift is ignored, so that e.g. `+` works on keyboards where it needs Shift.
+    pub fn char(c: char) -> Self {
+        Shortcut {
+            key: Key::Character(c.to_lowercase().collect::<String>().into()),
+            modifiers: Modifiers::empty(),
+        }
+    }
+
+    /// The same key pressed with the modifiers, e.g. `Shortcut::char('s').with(Modifiers::CTRL)`.
+    /// Shift is dropped from character shortcuts, which ignore it.
+    pub fn with(mut self, modifiers: Modifiers) -> Self {
+        self.modifiers |= modifiers;
+        if let Key::Character(_) = self.key {
+            self.modifiers.remove(Modifiers::SHIFT);
+        }
+        self
+    }
+
+    // whether the shortcut can't be typed into a text input
+    fn is_command(&self) -> bool {
+        self.modifiers.control() || self.modifiers.alt() || self.modifiers.logo()
+    }
+
+    fn matches(&self, key: &Key, modified_key: &Key, modifiers: Modifiers) -> bool {
+        match &self.key {
+            Key::Character(c) => {
+                let is_char =
+                    |key: &Key| matches!(key, Key::Character(k) if k.to_lowercase() == c.as_str());
+                (is_char(key) || is_char(modified_key))
+                    && modifiers.difference(Modifiers::SHIFT) == self.modifiers
+            }
+            _ => *key == self.key && modifiers == self.modifiers,
+        }
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CTRL, "Ctrl"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::LOGO, "Logo"),
+            (Modifiers::SHIFT, "Shift"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match &self.key {
+            Key::Character(c) => write!(f, "{}", c.to_uppercase()),
+            Key::Named(Named::ArrowLeft) => write!(f, "Left"),
+            Key::Named(Named::ArrowRight) => write!(f, "Right"),
+            Key::Named(Named::ArrowUp) => write!(f, "Up"),
+            Key::Named(Named::ArrowDown) => write!(f, "Down"),
+            Key::Named(named) => write!(f, "{named:?}"),
+            Key::Unidentified => write!(f, "?"),
+        }
+    }
+}
+
+/// A shortcut sending a message
+#[derive(Debug, Clone)]
+pub struct Binding<M> {
+    pub shortcut: Shortcut,
+    /// What the shortcut does, shown in the overlay
+    pub description: Cow<'static, str>,
+    pub message: M,
+}
+
+impl<M> Binding<M> {
+    pub fn new(shortcut: Shortcut, description: impl Into<Cow<'static, str>>, message: M) -> Self {
+        Binding {
+            shortcut,
+            description: description.into(),
+            message,
+        }
+    }
+}
+
+/// Shortcuts bound more than once or taken by the library, with a description of the conflict
+pub fn conflicts<M>(bindings: &[Binding<M>]) -> Vec<(Shortcut, String)> {
+    let mut conflicts = Vec::new();
+    for (i, binding) in bindings.iter().enumerate() {
+        let shortcut = &binding.shortcut;
+        if let Some((_, action)) = RESERVED
+            .iter()
+            .find(|(named, _)| shortcut.key == Key::Named(*named))
+        {
+            conflicts.push((
+                shortcut.clone(),
+                format!(
+                    "\"{}\" is taken by the library: {action}",
+                    binding.description
+                ),
+            ));
+        } else if let Some(first) = bindings[..i].iter().find(|b| b.shortcut == *shortcut) {
+            conflicts.push((
+                shortcut.clone(),
+                format!(
+                    "\"{}\" is hidden by \"{}\"",
+                    binding.description, first.description
+                ),
+            ));
+        }
+    }
+    conflicts
+}
+
+/// Whether the event is a key press, which can trigger a shortcut
+pub(crate) fn is_key_press(event: &Event) -> bool {
+    matches!(event, Event::Keyboard(keyboard::Event::KeyPressed { .. }))
+}
+
+/// The message of the first binding matching the key press
+pub(crate) fn find<M>(
+    bindings: Vec<Binding<M>>,
+    event: &Event,
+    text_input_focused: bool,
+) -> Option<M> {
+    let Event::Keyboard(keyboard::Event::KeyPressed {
+        key,
+        modified_key,
+        modifiers,
+        ..
+    }) = event
+    else {
+        return None;
+    };
+    bindings
+        .into_iter()
+        .filter(|binding| !text_input_focused || binding.shortcut.is_command())
+        .find(|binding| binding.shortcut.matches(key, modified_key, *modifiers))
+        .map(|binding| binding.message)
+}
+
+/// Whether the event is the key toggling the overlay
+pub(crate) fn is_help_key(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            key: Key::Named(Named::F1),
+            ..
+        })
+    )
+}
+
+/// Draws the overlay listing the shortcuts of the program and of the library
+pub(crate) fn draw_help<M>(renderer: &mut Renderer, bindings: &[Binding<M>], bounds: Size) {
+    let lines: Vec<String> = std::iter::once("Keyboard shortcuts".to_owned())
+        .chain(bindings.iter().map(|binding| {
+            let shortcut = binding.shortcut.to_string();
+            format!("{shortcut:SHORTCUT_COLUMN$}{}", binding.description)
+        }))
+        .chain(RESERVED.iter().map(|(named, action)| {
+            let shortcut = Shortcut::named(*named).to_string();
+            format!("{shortcut:SHORTCUT_COLUMN$}{action}")
+        }))
+        .collect();
+
+    let size = Size::new(
+        bounds.width.min(480.0),
+        STYLE.height(lines.len()).min(bounds.height),
+    );
+    let panel = Rectangle::new(
+        Point::new(
+            (bounds.width - size.width) / 2.0,
+            (bounds.height - size.height) / 2.0,
+        ),
+        size,
+    );
+    panel::draw_text_panel(renderer, lines, panel, &STYLE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key, modified_key: Key, modifiers: Modifiers) -> Event {
+        Event::Keyboard(keyboard::Event::KeyPressed {
+            key,
+            modified_key,
+            physical_key: keyboard::key::Physical::Unidentified(
+                keyboard::key::NativeCode::Unidentified,
+            ),
+            location: keyboard::Location::Standard,
+            modifiers,
+            text: None,
+        })
+    }
+
+    fn char_key(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    #[test]
+    fn chars_ignore_case_and_shift() {
+        let shortcut = Shortcut::char('S').with(Modifiers::CTRL);
+        assert!(shortcut.matches(&char_key("s"), &char_key("s"), Modifiers::CTRL));
+        assert!(shortcut.matches(
+            &char_key("s"),
+            &char_key("S"),
+            Modifiers::CTRL | Modifiers::SHIFT
+        ));
+        assert!(!shortcut.matches(&char_key("s"), &char_key("s"), Modifiers::empty()));
+        assert!(!shortcut.matches(
+            &char_key("s"),
+            &char_key("s"),
+            Modifiers::CTRL | Modifiers::ALT
+        ));
+    }
+
+    #[test]
+    fn chars_drop_shift() {
+        let shortcut = Shortcut::char('s').with(Modifiers::CTRL | Modifiers::SHIFT);
+        assert_eq!(shortcut, Shortcut::char('s').with(Modifiers::CTRL));
+        assert_eq!(shortcut.to_string(), "Ctrl+S");
+        assert!(shortcut.matches(
+            &char_key("s"),
+            &char_key("S"),
+            Modifiers::CTRL | Modifiers::SHIFT
+        ));
+    }
+
+    #[test]
+    fn chars_match_the_modified_key() {
+        // `+` typed as Shift with `=` on a US layout
+        let plus = Shortcut::char('+');
+        assert!(plus.matches(&char_key("="), &char_key("+"), Modifiers::SHIFT));
+        assert!(!plus.matches(&char_key("="), &char_key("="), Modifiers::empty()));
+        assert!(Shortcut::char('=').matches(&char_key("="), &char_key("+"), Modifiers::SHIFT));
+    }
+
+    #[test]
+    fn named_keys_need_exact_modifiers() {
+        let left = Shortcut::named(Named::ArrowLeft);
+        let key = Key::Named(Named::ArrowLeft);
+        assert!(left.matches(&key, &key, Modifiers::empty()));
+        assert!(!left.matches(&key, &key, Modifiers::SHIFT));
+        assert!(
+            left.clone()
+                .with(Modifiers::SHIFT)
+                .matches(&key, &key, Modifiers::SHIFT)
+        );
+    }
+
+    #[test]
+    fn find_skips_typed_shortcuts_in_text_inputs() {
+        let bindings = || {
+            vec![
+                Binding::new(Shortcut::char('n'), "Next", 1),
+                Binding::new(Shortcut::char('n').with(Modifiers::CTRL), "New", 2),
+                Binding::new(Shortcut::char('n'), "Hidden", 3),
+            ]
+        };
+        let n = press(char_key("n"), char_key("n"), Modifiers::empty());
+        let ctrl_n = press(char_key("n"), char_key("n"), Modifiers::CTRL);
+        assert_eq!(find(bindings(), &n, false), Some(1));
+        assert_eq!(find(bindings(), &n, true), None);
+        assert_eq!(find(bindings(), &ctrl_n, true), Some(2));
+        let release = Event::Keyboard(keyboard::Event::KeyReleased {
+            key: char_key("n"),
+            location: keyboard::Location::Standard,
+            modifiers: Modifiers::empty(),
+        });
+        assert_eq!(find(bindings(), &release, false), None);
+    }
+
+    #[test]
+    fn conflicts_with_bindings_and_library() {
+        let bindings = [
+            Binding::new(Shortcut::char('N'), "Next", ()),
+            Binding::new(Shortcut::char('n').with(Modifiers::CTRL), "New", ()),
+            Binding::new(Shortcut::char('n'), "Hidden", ()),
+            Binding::new(Shortcut::named(Named::F1), "Help", ()),
+            Binding::new(
+                Shortcut::named(Named::F1).with(Modifiers::CTRL),
+                "Help too",
+                (),
+            ),
+        ];
+        let conflicts: Vec<_> = conflicts(&bindings)
+            .into_iter()
+            .map(|(shortcut, conflict)| format!("{shortcut}: {conflict}"))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                r#"N: "Hidden" is hidden by "Next""#,
+                r#"F1: "Help" is taken by the library: Show keyboard shortcuts"#,
+                r#"Ctrl+F1: "Help too" is taken by the library: Show keyboard shortcuts"#,
+            ]
+        );
+        assert!(super::conflicts(&bindings[..2]).is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+//! Execution state of a program, which keeps the user interface cache between frames.
+//!
+//! Adapted from `iced_runtime`'s `program::State`
+//! (https://github.com/iced-rs/iced/blob/0.13/runtime/src/program/state.rs)
+//! Copyright 2019 Héctor Ramón, Iced contributors, licensed under MIT license
+//!
+//! Unlike the original, [`State::update`] also returns when the widgets want to be redrawn
+//! (e.g. a blinking cursor in a text input), so that the backends can schedule the next frame,
+//! and finds the focused text input for the input method.
+
+use iced_runtime::user_interface::{self, UserInterface};
+use iced_runtime::{Debug, Program, Task};
+use iced_widget::core::widget::operation::{self, Operation};
+use iced_widget::core::window::RedrawRequest;
+use iced_widget::core::{Clipboard, Event, Rectangle, Size, event, mouse, renderer};
+
+use crate::{ime, span};
+
+pub(crate) struct State<P>
+where
+    P: Program + 'static,
+{
+    program: P,
+    cache: Option<user_interface::Cache>,
+    queued_events: Vec<Event>,
+    queued_messages: Vec<P::Message>,
+    mouse_interaction: mouse::Interaction,
+    text_input_area: Option<Rectangle>,
+}
+
+impl<P> State<P>
+where
+    P: Program + 'static,
+{
+    pub(crate) fn new(
+        mut program: P,
+        bounds: Size,
+        renderer: &mut P::Renderer,
+        debug: &mut Debug,
+    ) -> Self {
+        let user_interface = build_user_interface(
+            &mut program,
+            user_interface::Cache::default(),
+            renderer,
+            bounds,
+            debug,
+        );
+
+        let cache = Some(user_interface.into_cache());
+
+        State {
+            program,
+            cache,
+            queued_events: Vec::new(),
+            queued_messages: Vec::new(),
+            mouse_interaction: mouse::Interaction::None,
+            text_input_area: None,
+        }
+    }
+
+    pub(crate) fn program(&self) -> &P {
+        &self.program
+    }
+
+    pub(crate) fn program_mut(&mut self) -> &mut P {
+        &mut self.program
+    }
+
+    pub(crate) fn queue_event(&mut self, event: Event) {
+        self.queued_events.push(event);
+    }
+
+    pub(crate) fn queue_message(&mut self, message: P::Message) {
+        self.queued_messages.push(message);
+    }
+
+    pub(crate) fn is_queue_empty(&self) -> bool {
+        self.queued_events.is_empty() && self.queued_messages.is_empty()
+    }
+
+    pub(crate) fn mouse_interaction(&self) -> mouse::Interaction {
+        self.mouse_interaction
+    }
+
+    /// Where the focused text input is, see [`ime::focused_text_input`]
+    pub(crate) fn text_input_area(&self) -> Option<Rectangle> {
+        self.text_input_area
+    }
+
+    /// Processes all the queued events and messages, rebuilding and redrawing the widgets
+    /// if necessary.
+    ///
+    /// Returns the events that were not captured by any widget, the task produced by
+    /// the program (if it was updated), and when the widgets want to be redrawn next.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn update(
+        &mut self,
+        bounds: Size,
+        cursor: mouse::Cursor,
+        renderer: &mut P::Renderer,
+        theme: &P::Theme,
+        style: &renderer::Style,
+        clipboard: &mut dyn Clipboard,
+        debug: &mut Debug,
+    ) -> (Vec<Event>, Option<Task<P::Message>>, Option<RedrawRequest>) {
+        let mut user_interface = build_user_interface(
+            &mut self.program,
+            self.cache.take().unwrap(),
+            renderer,
+            bounds,
+            debug,
+        );
+
+        debug.event_processing_started();
+        let mut messages = Vec::new();
+
+        let (ui_state, event_statuses) = user_interface.update(
+            &self.queued_events,
+            cursor,
+            renderer,
+            clipboard,
+            &mut messages,
+        );
+
+        let redraw_request = match ui_state {
+            user_interface::State::Updated { redraw_request } => redraw_request,
+            // the interface will be rebuilt on the next update
+            user_interface::State::Outdated => Some(RedrawRequest::NextFrame),
+        };
+
+        let uncaptured_events = self
+            .queued_events
+            .iter()
+            .zip(event_statuses)
+            .filter_map(|(event, status)| matches!(status, event::Status::Ignored).then_some(event))
+            .cloned()
+            .collect();
+
+        self.queued_events.clear();
+        messages.append(&mut self.queued_messages);
+        debug.event_processing_finished();
+
+        let task = if messages.is_empty() {
+            debug.draw_started();
+            self.mouse_interaction = user_interface.draw(renderer, theme, style, cursor);
+            debug.draw_finished();
+
+            self.text_input_area = ime::focused_text_input(&mut user_interface, renderer, bounds);
+            self.cache = Some(user_interface.into_cache());
+
+            None
+        } else {
+            // when there are messages, the interface has to be rebuilt after updating
+            // the program
+            let temp_cache = user_interface.into_cache();
+
+            let tasks = Task::batch(messages.into_iter().map(|message| {
+                debug.log_message(&message);
+
+                debug.update_started();
+                let span = span!("update");
+                let task = self.program.update(message);
+                drop(span);
+                debug.update_finished();
+
+                task
+            }));
+
+            let mut user_interface =
+                build_user_interface(&mut self.program, temp_cache, renderer, bounds, debug);
+
+            debug.draw_started();
+            self.mouse_interaction = user_interface.draw(renderer, theme, style, cursor);
+            debug.draw_finished();
+
+            self.text_input_area = ime::focused_text_input(&mut user_interface, renderer, bounds);
+            self.cache = Some(user_interface.into_cache());
+
+            Some(tasks)
+        };
+
+        (uncaptured_events, task, redraw_request)
+    }
+
+    /// Applies widget operations, e.g. focusing or scrolling
+    pub(crate) fn operate(
+        &mut self,
+        renderer: &mut P::Renderer,
+        operations: impl Iterator<Item = Box<dyn Operation>>,
+        bounds: Size,
+        debug: &mut Debug,
+    ) {
+        let mut user_interface = build_user_interface(
+            &mut self.program,
+            self.cache.take().unwrap(),
+            renderer,
+            bounds,
+            debug,
+        );
+
+        for operation in operations {
+            let mut current_operation = Some(operation);
+
+            while let Some(mut operation) = current_operation.take() {
+                user_interface.operate(renderer, operation.as_mut());
+
+                match operation.finish() {
+                    operation::Outcome::None => {}
+                    operation::Outcome::Some(()) => {}
+                    operation::Outcome::Chain(next) => {
+                        current_operation = Some(next);
+                    }
+                };
+            }
+        }
+
+        self.cache = Some(user_interface.into_cache());
+    }
+}
+
+fn build_user_interface<'a, P: Program>(
+    program: &'a mut P,
+    cache: user_interface::Cache,
+    renderer: &mut P::Renderer,
+    size: Size,
+    debug: &mut Debug,
+) -> UserInterface<'a, P::Message, P::Theme, P::Renderer> {
+    debug.view_started();
+    let span = span!("view");
+    let view = program.view();
+    drop(span);
+    debug.view_finished();
+
+    debug.layout_started();
+    let user_interface = UserInterface::build(view, size, cache, renderer);
+    debug.layout_finished();
+
+    user_interface
+}
@@ -0,0 +1,171 @@
+//! Software surface of a window, which redraws and presents only the regions that changed since
+//! the frame currently in the buffer.
+//!
+//! The damage tracking is adapted from `iced_tiny_skia`'s compositor
+//! (https://github.com/iced-rs/iced/blob/0.13/tiny_skia/src/window/compositor.rs)
+//! Copyright 2019 Héctor Ramón, Iced contributors, licensed under MIT license
+
+use std::collections::VecDeque;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use iced_tiny_skia::Layer;
+use iced_widget::Renderer;
+use iced_widget::core::{Color, Rectangle, Size};
+use iced_widget::graphics::{Viewport, damage};
+
+use crate::platform::Window;
+
+type SoftbufferSurface = softbuffer::Surface<Arc<Window>, Arc<Window>>;
+
+pub(crate) struct Surface {
+    surface: SoftbufferSurface,
+    // dropped after the surface, which may still use it on some platforms
+    _context: softbuffer::Context<Arc<Window>>,
+    clip_mask: tiny_skia::Mask,
+    // layers of the recently presented frames, the newest first
+    layer_stack: VecDeque<Vec<Layer>>,
+    background_color: Color,
+    max_age: u8,
+    // physical size and scale factor the buffers were configured for
+    configured: Option<(Size<u32>, f64)>,
+}
+
+impl Surface {
+    pub(crate) fn new(w: Arc<Window>) -> Result<Self, softbuffer::SoftBufferError> {
+        let context = softbuffer::Context::new(w.clone())?;
+        Ok(Self {
+            surface: softbuffer::Surface::new(&context, w)?,
+            _context: context,
+            clip_mask: tiny_skia::Mask::new(1, 1).expect("Non-zero size"),
+            layer_stack: VecDeque::new(),
+            background_color: Color::BLACK,
+            max_age: 0,
+            configured: None,
+        })
+    }
+
+    /// Resizes the buffers if the size or scale changed since the last frame. The previous frames
+    /// are forgotten then, so the next one is drawn whole.
+    fn configure(&mut self, viewport: &Viewport) -> Result<(), softbuffer::SoftBufferError> {
+        let size = viewport.physical_size();
+        let configuration = Some((size, viewport.scale_factor()));
+        if self.configured == configuration {
+            return Ok(());
+        }
+        self.surface.resize(
+            NonZeroU32::new(size.width).expect("Non-zero width"),
+            NonZeroU32::new(size.height).expect("Non-zero height"),
+        )?;
+        self.clip_mask = tiny_skia::Mask::new(size.width, size.height).expect("Non-zero size");
+        self.layer_stack.clear();
+        self.configured = configuration;
+        Ok(())
+    }
+
+    /// Draws and presents the frame. If that fails, the previous frames are forgotten, so that
+    /// the next one is drawn whole.
+    pub(crate) fn present(
+        &mut self,
+        renderer: &mut Renderer,
+        viewport: &Viewport,
+        background_color: Color,
+    ) -> Result<(), softbuffer::SoftBufferError> {
+        let result = self.draw_and_present(renderer, viewport, background_color);
+        if result.is_err() {
+            self.layer_stack.clear();
+            self.configured = None;
+        }
+        result
+    }
+
+    fn draw_and_present(
+        &mut self,
+        renderer: &mut Renderer,
+        viewport: &Viewport,
+        background_color: Color,
+    ) -> Result<(), softbuffer::SoftBufferError> {
+        let physical_size = viewport.physical_size();
+        if physical_size.width == 0 || physical_size.height == 0 {
+            // e.g. a minimized window, there is nothing to draw into
+            return Ok(());
+        }
+        self.configure(viewport)?;
+
+        let mut buffer = self.surface.buffer_mut()?;
+
+        // the buffer contains the frame presented `age` frames ago, or garbage if the age is 0
+        let last_layers = {
+            let age = buffer.age();
+
+            self.max_age = self.max_age.max(age);
+            self.layer_stack.truncate(self.max_age as usize);
+
+            if age > 0 {
+                self.layer_stack.get(age as usize - 1)
+            } else {
+                None
+            }
+        };
+
+        let bounds = Rectangle::with_size(viewport.logical_size());
+        let damage = last_layers
+            .and_then(|last_layers| {
+                (self.background_color == background_color).then(|| {
+                    damage::diff(
+                        last_layers,
+                        renderer.layers(),
+                        |layer| vec![layer.bounds],
+                        Layer::damage,
+                    )
+                })
+            })
+            .unwrap_or_else(|| vec![bounds]);
+
+        if damage.is_empty() {
+            return Ok(());
+        }
+
+        self.layer_stack.push_front(renderer.layers().to_vec());
+        self.background_color = background_color;
+
+        let damage = damage::group(damage, bounds);
+
+        let mut pixels = tiny_skia::PixmapMut::from_bytes(
+            bytemuck::cast_slice_mut(&mut buffer),
+            physical_size.width,
+            physical_size.height,
+        )
+        .expect("Buffer matches the configured size");
+
+        renderer.draw::<String>(
+            &mut pixels,
+            &mut self.clip_mask,
+            viewport,
+            &damage,
+            background_color,
+            &[],
+        );
+
+        let damage: Vec<_> = damage
+            .into_iter()
+            .filter_map(|region| physical_rect(region, viewport.scale_factor(), physical_size))
+            .collect();
+        buffer.present_with_damage(&damage)
+    }
+}
+
+/// Converts a damaged region in logical coordinates to the pixels covering it
+fn physical_rect(region: Rectangle, scale: f64, size: Size<u32>) -> Option<softbuffer::Rect> {
+    let scale = scale as f32;
+    let x0 = ((region.x * scale).floor().max(0.0) as u32).min(size.width);
+    let y0 = ((region.y * scale).floor().max(0.0) as u32).min(size.height);
+    let x1 = (((region.x + region.width) * scale).ceil().max(0.0) as u32).min(size.width);
+    let y1 = (((region.y + region.height) * scale).ceil().max(0.0) as u32).min(size.height);
+    Some(softbuffer::Rect {
+        x: x0,
+        y: y0,
+        width: NonZeroU32::new(x1.saturating_sub(x0))?,
+        height: NonZeroU32::new(y1.saturating_sub(y0))?,
+    })
+}
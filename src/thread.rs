@@ -0,0 +1,258 @@
+//! Threads of the program supervised by the library, so that it doesn't have to join them in
+//! [`ProgramExt::on_exit`].
+//!
+//! When the program exits, the supervised threads and the [`worker`]s are told to stop and
+//! joined, but only for [`EXIT_TIMEOUT`] in total. Threads still running after that are
+//! reported and left behind, so that a stuck thread can't keep the window hanging.
+//!
+//! Blocking work of tasks, like reading and decoding files, should run on the shared pool of
+//! threads of [`spawn_blocking`] rather than on the executor of the tasks, which would wait for
+//! it with all the other tasks and subscriptions.
+//!
+//! Threads spawned while the library runs code of the program, i.e. in `create_app`, `update`
+//! and the hooks of [`ProgramExt`], belong to that program, as well as the threads spawned by
+//! them in turn. Other threads are not supervised.
+//!
+//! [`ProgramExt`]: crate::ProgramExt
+//! [`ProgramExt::on_exit`]: crate::ProgramExt::on_exit
+//! [`worker`]: crate::worker
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak, mpsc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use iced_runtime::futures::futures::channel::oneshot;
+use iced_runtime::futures::futures::future;
+
+use crate::{error, warn};
+
+/// How long the exiting program waits for all of its threads and workers together
+pub const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// how often a joined thread is checked whether it finished
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+thread_local! {
+    // supervisor of the program whose code is running on this thread
+    static CURRENT: RefCell<Option<Supervisor>> = const { RefCell::new(None) };
+}
+
+/// Something stopped together with the program, like a worker
+pub(crate) trait Stop: Send + Sync {
+    fn stop(&self, deadline: Instant);
+}
+
+/// Threads and workers of one program, to stop when it exits
+#[derive(Clone, Default)]
+pub(crate) struct Supervisor(Arc<Mutex<Supervised>>);
+
+#[derive(Default)]
+struct Supervised {
+    threads: Vec<(JoinHandle<()>, Stopping)>,
+    // workers stop when they are dropped, the program doesn't keep them alive
+    workers: Vec<Weak<dyn Stop>>,
+}
+
+/// Makes the previous supervisor current again when dropped, see [`Supervisor::enter`]
+#[must_use = "the supervisor is current until the guard is dropped"]
+pub(crate) struct Entered(Option<Supervisor>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.set(self.0.take());
+    }
+}
+
+impl Supervisor {
+    /// Makes threads and workers created on this thread belong to this supervisor, until the
+    /// guard is dropped
+    pub(crate) fn enter(&self) -> Entered {
+        Entered(CURRENT.replace(Some(self.clone())))
+    }
+
+    /// The supervisor entered on this thread, if any
+    pub(crate) fn current() -> Option<Supervisor> {
+        CURRENT.with_borrow(Clone::clone)
+    }
+
+    /// Registers the worker with the current supervisor, if any
+    pub(crate) fn add_worker(worker: Weak<dyn Stop>) {
+        if let Some(supervisor) = Self::current() {
+            let mut supervised = supervisor.0.lock().unwrap();
+            supervised
+                .workers
+                .retain(|worker| worker.strong_count() > 0);
+            supervised.workers.push(worker);
+        }
+    }
+
+    /// Stops the threads and workers of the program, waiting for them until the deadline
+    pub(crate) fn stop_all(&self, deadline: Instant) {
+        // taken out first, so that the stopped ones can't deadlock on the lock
+        let Supervised { threads, workers } = std::mem::take(&mut *self.0.lock().unwrap());
+        for worker in workers {
+            if let Some(worker) = worker.upgrade() {
+                worker.stop(deadline);
+            }
+        }
+        for (_, stopping) in &threads {
+            stopping.0.store(true, Ordering::Release);
+        }
+        for (handle, _) in threads {
+            join(handle, deadline);
+        }
+    }
+}
+
+/// Tells a supervised thread that the program is exiting and the thread should end
+#[derive(Debug, Clone, Default)]
+pub struct Stopping(Arc<AtomicBool>);
+
+impl Stopping {
+    pub fn is_stopping(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Spawns a named thread, which is told to stop and joined when the program exits. It should
+/// check [`Stopping::is_stopping`] regularly, e.g. after each piece of work.
+pub fn spawn(name: &str, f: impl FnOnce(Stopping) + Send + 'static) {
+    let stopping = Stopping::default();
+    let supervisor = Supervisor::current();
+    let handle = std::thread::Builder::new()
+        .name(name.to_owned())
+        .spawn({
+            let stopping = stopping.clone();
+            let supervisor = supervisor.clone();
+            move || {
+                let _entered = supervisor.as_ref().map(Supervisor::enter);
+                f(stopping)
+            }
+        })
+        .expect("failed to spawn thread");
+    if let Some(supervisor) = supervisor {
+        let mut supervised = supervisor.0.lock().unwrap();
+        supervised
+            .threads
+            .retain(|(handle, _)| !handle.is_finished());
+        supervised.threads.push((handle, stopping));
+    }
+}
+
+type BlockingJob = Box<dyn FnOnce() + Send>;
+
+/// Runs `f` on a shared pool of threads, returning a future resolving to its result, e.g.
+/// `Task::perform(thread::spawn_blocking(move || load(&path)), Message::Loaded)`.
+///
+/// The pool has a thread per CPU. Its jobs are not stopped when the program exits, and the
+/// future never resolves if `f` panics.
+pub fn spawn_blocking<R: Send + 'static>(
+    f: impl FnOnce() -> R + Send + 'static,
+) -> impl Future<Output = R> {
+    static POOL: OnceLock<mpsc::Sender<BlockingJob>> = OnceLock::new();
+
+    let pool = POOL.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<BlockingJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("blocking-{i}"))
+                .spawn(move || {
+                    loop {
+                        // the lock is released before running the job
+                        let job = receiver.lock().unwrap().recv();
+                        let Ok(job) = job else {
+                            return;
+                        };
+                        if std::panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            error!("Blocking job panicked");
+                        }
+                    }
+                })
+                .expect("failed to spawn thread");
+        }
+        sender
+    });
+    let (sender, receiver) = oneshot::channel();
+    // the pool threads never end, so they always receive the job
+    let _ = pool.send(Box::new(move || {
+        // the task waiting for the result may have been dropped meanwhile
+        let _ = sender.send(f());
+    }));
+    async move {
+        match receiver.await {
+            Ok(result) => result,
+            Err(oneshot::Canceled) => future::pending().await,
+        }
+    }
+}
+
+/// Joins the thread if it finishes before the deadline, otherwise reports it and leaves it
+/// running
+pub(crate) fn join(handle: JoinHandle<()>, deadline: Instant) {
+    while !handle.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let name = handle.thread().name().unwrap_or("unnamed").to_owned();
+    if !handle.is_finished() {
+        warn!("Thread {name} didn't stop in time, leaving it running");
+        return;
+    }
+    if let Err(e) = handle.join() {
+        error!("Thread {name} panicked: {e:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    // spawns a thread running until it is told to stop, counting the stopped threads
+    fn spawn_counted(stopped: &Arc<AtomicUsize>) {
+        let stopped = stopped.clone();
+        spawn("test", move |stopping| {
+            while !stopping.is_stopping() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            stopped.fetch_add(1, Ordering::AcqRel);
+        });
+    }
+
+    #[test]
+    fn supervisors_stop_only_their_threads() {
+        let (first, second) = (Supervisor::default(), Supervisor::default());
+        let first_stopped = Arc::new(AtomicUsize::new(0));
+        let second_stopped = Arc::new(AtomicUsize::new(0));
+        {
+            let _entered = first.enter();
+            spawn_counted(&first_stopped);
+            {
+                let _entered = second.enter();
+                spawn_counted(&second_stopped);
+            }
+            // threads spawned by supervised threads are supervised too
+            let first_stopped = first_stopped.clone();
+            spawn("parent", move |_| spawn_counted(&first_stopped));
+        }
+        assert!(Supervisor::current().is_none());
+
+        // the parent thread ends right away, its child is registered by then
+        while first.0.lock().unwrap().threads.len() < 3 {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        first.stop_all(Instant::now() + EXIT_TIMEOUT);
+        assert_eq!(first_stopped.load(Ordering::Acquire), 2);
+        assert_eq!(second_stopped.load(Ordering::Acquire), 0);
+
+        second.stop_all(Instant::now() + EXIT_TIMEOUT);
+        assert_eq!(second_stopped.load(Ordering::Acquire), 1);
+    }
+}
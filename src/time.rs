@@ -0,0 +1,140 @@
+//! Timers for use in [`Task`]s and [`Subscription`]s.
+//!
+//! All timers are served by a single background thread, which wakes the waiting futures
+//! when their deadline passes.
+//!
+//! [`Task`]: iced_runtime::Task
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::task::{Context, Poll, Waker};
+
+use iced_runtime::futures::futures::stream::{self, StreamExt};
+use iced_runtime::futures::{BoxStream, Subscription, subscription};
+
+pub use std::time::{Duration, Instant};
+
+struct Entry(Instant, Waker);
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for Entry {}
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+fn timer_thread(receiver: mpsc::Receiver<Entry>) {
+    let mut queue = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while let Some(Reverse(Entry(deadline, _))) = queue.peek() {
+            if *deadline > now {
+                break;
+            }
+            let Reverse(Entry(_, waker)) = queue.pop().unwrap();
+            waker.wake();
+        }
+        let entry = match queue.peek() {
+            Some(Reverse(Entry(deadline, _))) => {
+                match receiver.recv_timeout(deadline.saturating_duration_since(now)) {
+                    Ok(entry) => entry,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match receiver.recv() {
+                Ok(entry) => entry,
+                Err(_) => return,
+            },
+        };
+        queue.push(Reverse(entry));
+    }
+}
+
+fn register(deadline: Instant, waker: Waker) {
+    static TIMER: OnceLock<mpsc::Sender<Entry>> = OnceLock::new();
+    let sender = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("iced-timer".to_owned())
+            .spawn(move || timer_thread(receiver))
+            .expect("Failed to spawn timer thread");
+        sender
+    });
+    sender.send(Entry(deadline, waker)).unwrap();
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`]
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            register(self.deadline, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Completes after the given duration
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Completes at the given instant
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline }
+}
+
+/// Returns a [`Subscription`] that produces messages at a set interval.
+///
+/// The first message is produced after `duration`, then every `duration` after that.
+/// Ticks that were missed (e.g. because the executor was busy) are skipped.
+pub fn every(duration: Duration) -> Subscription<Instant> {
+    subscription::from_recipe(Every(duration))
+}
+
+#[derive(Debug)]
+struct Every(Duration);
+
+impl subscription::Recipe for Every {
+    type Output = Instant;
+
+    fn hash(&self, state: &mut subscription::Hasher) {
+        std::any::TypeId::of::<Self>().hash(state);
+        self.0.hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: subscription::EventStream) -> BoxStream<Instant> {
+        let duration = self.0;
+        stream::unfold(Instant::now() + duration, move |deadline| async move {
+            sleep_until(deadline).await;
+            let now = Instant::now();
+            let next = (deadline + duration).max(now);
+            Some((now, next))
+        })
+        .boxed()
+    }
+}
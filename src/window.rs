@@ -0,0 +1,133 @@
+//! Tasks changing the window of the program at runtime. Its initial state is set in
+//! [`crate::WindowOptions`].
+//!
+//! The tasks of [`iced_runtime::window`] which resize, move, maximize or minimize the window,
+//! or change its mode and decorations, work too.
+//!
+//! Not everything is supported on HelenOS: the window can't be moved, its mode, decorations
+//! and size limits can't be changed after it is created, there is no maximum size, and the
+//! cursor can't be hidden.
+
+use std::any::Any;
+use std::convert::Infallible;
+
+use iced_runtime::{Action, Task, task};
+use iced_widget::core::widget::{self, Operation};
+use iced_widget::core::{Point, Rectangle, Size};
+
+pub use iced_widget::core::window::{Event, Id, Mode};
+
+/// A change of the window requested by a task, sizes and positions are in logical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Request {
+    Resize(Size),
+    Move(Point),
+    MinSize(Option<Size>),
+    MaxSize(Option<Size>),
+    Resizable(bool),
+    Decorations(bool),
+    ToggleDecorations,
+    Mode(Mode),
+    Maximize(bool),
+    ToggleMaximize,
+    Minimize(bool),
+    CursorVisible(bool),
+}
+
+/// Resizes the drawable area of the window
+pub fn resize<T>(size: Size) -> Task<T> {
+    request(Request::Resize(size))
+}
+
+/// Moves the top left corner of the window to the position on the screen
+pub fn move_to<T>(position: Point) -> Task<T> {
+    request(Request::Move(position))
+}
+
+/// Sets the size the user can't shrink the drawable area below, `None` to remove the limit
+pub fn set_min_size<T>(size: Option<Size>) -> Task<T> {
+    request(Request::MinSize(size))
+}
+
+/// Sets the size the user can't enlarge the drawable area beyond, `None` to remove the limit
+pub fn set_max_size<T>(size: Option<Size>) -> Task<T> {
+    request(Request::MaxSize(size))
+}
+
+pub fn set_resizable<T>(resizable: bool) -> Task<T> {
+    request(Request::Resizable(resizable))
+}
+
+/// Shows or hides the title bar and borders of the window
+pub fn set_decorations<T>(decorations: bool) -> Task<T> {
+    request(Request::Decorations(decorations))
+}
+
+pub fn set_fullscreen<T>(fullscreen: bool) -> Task<T> {
+    request(Request::Mode(if fullscreen {
+        Mode::Fullscreen
+    } else {
+        Mode::Windowed
+    }))
+}
+
+pub fn maximize<T>(maximized: bool) -> Task<T> {
+    request(Request::Maximize(maximized))
+}
+
+pub fn minimize<T>(minimized: bool) -> Task<T> {
+    request(Request::Minimize(minimized))
+}
+
+/// Hides the mouse cursor while it is over the window, or shows it again. The shape of the
+/// cursor is still given by [`mouse::Interaction`](iced_widget::core::mouse::Interaction) of
+/// the widgets.
+pub fn set_cursor_visible<T>(visible: bool) -> Task<T> {
+    request(Request::CursorVisible(visible))
+}
+
+// iced has no action for most of these changes, so they travel to the backend as a widget
+// operation, which hands the request over to the state it is given in `custom`
+fn request<T>(request: Request) -> Task<T> {
+    let action: Action<Infallible> = Action::widget(RequestOperation(request));
+    task::effect(action)
+}
+
+struct RequestOperation(Request);
+
+impl Operation for RequestOperation {
+    fn container(
+        &mut self,
+        _id: Option<&widget::Id>,
+        _bounds: Rectangle,
+        _operate_on_children: &mut dyn FnMut(&mut dyn Operation),
+    ) {
+    }
+
+    fn custom(&mut self, state: &mut dyn Any, _id: Option<&widget::Id>) {
+        if let Some(requests) = state.downcast_mut::<Requests>() {
+            requests.0.push(self.0);
+        }
+    }
+}
+
+/// Collects the window requests carried by a widget operation
+#[derive(Debug, Default)]
+pub(crate) struct Requests(pub(crate) Vec<Request>);
+
+impl Requests {
+    pub(crate) fn take_from(operation: &mut dyn Operation) -> Vec<Request> {
+        let mut requests = Requests::default();
+        operation.custom(&mut requests, None);
+        requests.0
+    }
+}
+
+/// Converts a size in logical pixels to physical ones
+pub(crate) fn to_physical(size: Size, scale_factor: f64) -> Size<u32> {
+    let scale = scale_factor as f32;
+    Size::new(
+        (size.width * scale).round() as u32,
+        (size.height * scale).round() as u32,
+    )
+}
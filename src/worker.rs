@@ -0,0 +1,317 @@
+//! A background thread computing the results of jobs submitted by the program, e.g. decoding
+//! or resizing images, so that the interface stays responsive meanwhile.
+//!
+//! Jobs run one at a time, in the order they were submitted. A coalescing worker only keeps
+//! the latest pending job, for jobs that make the previous ones useless (like resizing to the
+//! zoom picked last). [`Worker::cancel`] drops the pending jobs and starts a new version, the
+//! results of jobs from older versions are not sent to the program.
+//!
+//! Workers created in code of the program run by the library, e.g. in `create_app` or in
+//! `update`, are stopped and joined automatically after [`ProgramExt::on_exit`], like the
+//! supervised [`thread`]s. The others are stopped when they are dropped. A worker whose job
+//! doesn't end within [`thread::EXIT_TIMEOUT`] is left running.
+//!
+//! [`ProgramExt::on_exit`]: crate::ProgramExt::on_exit
+//! [`thread`]: crate::thread
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use crate::SendMsgFn;
+use crate::thread::{self, Stop, Supervisor};
+
+/// A thread running the jobs of type `Job` and sending their results as `Msg` to the program
+pub struct Worker<Job, Msg> {
+    shared: Arc<Shared<Job>>,
+    _msg: std::marker::PhantomData<fn() -> Msg>,
+}
+
+/// What a running job can find out about itself
+#[derive(Debug)]
+pub struct JobContext {
+    version: u64,
+    current_version: Arc<AtomicU64>,
+}
+
+impl JobContext {
+    /// Version of the worker the job was submitted in
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Whether the job was cancelled, so that a long computation can end early. Its result
+    /// is not sent anyway.
+    pub fn is_cancelled(&self) -> bool {
+        self.current_version.load(Ordering::Acquire) != self.version
+    }
+}
+
+struct Shared<Job> {
+    queue: Mutex<Queue<Job>>,
+    condvar: Condvar,
+    version: Arc<AtomicU64>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Queue<Job> {
+    // pending jobs, with the versions they were submitted in
+    jobs: VecDeque<(u64, Job)>,
+    coalesce: bool,
+    stopped: bool,
+}
+
+impl<Job: Send> Stop for Shared<Job> {
+    fn stop(&self, deadline: Instant) {
+        Shared::stop(self, deadline);
+    }
+}
+
+impl<Job> Shared<Job> {
+    fn stop(&self, deadline: Instant) {
+        {
+            let mut queue = self.queue.lock().unwrap();
+            queue.stopped = true;
+            queue.jobs.clear();
+        }
+        self.version.fetch_add(1, Ordering::AcqRel);
+        self.condvar.notify_one();
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            // a job can stop its own worker, it just can't wait for itself
+            if handle.thread().id() != std::thread::current().id() {
+                thread::join(handle, deadline);
+            }
+        }
+    }
+}
+
+impl<Job: Send + 'static, Msg: Send + 'static> Worker<Job, Msg> {
+    /// Starts a worker running every submitted job. `run` computes the message sent to the
+    /// program for a job, or `None` to send nothing.
+    pub fn new(
+        send_msg: SendMsgFn<Msg>,
+        run: impl FnMut(Job, &JobContext) -> Option<Msg> + Send + 'static,
+    ) -> Self {
+        Self::spawn(false, send_msg, run)
+    }
+
+    /// Starts a worker which only runs the latest of the jobs submitted while it was busy
+    pub fn coalescing(
+        send_msg: SendMsgFn<Msg>,
+        run: impl FnMut(Job, &JobContext) -> Option<Msg> + Send + 'static,
+    ) -> Self {
+        Self::spawn(true, send_msg, run)
+    }
+
+    fn spawn(
+        coalesce: bool,
+        send_msg: SendMsgFn<Msg>,
+        mut run: impl FnMut(Job, &JobContext) -> Option<Msg> + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                coalesce,
+                stopped: false,
+            }),
+            condvar: Condvar::new(),
+            version: Arc::new(AtomicU64::new(0)),
+            handle: Mutex::new(None),
+        });
+        let supervisor = Supervisor::current();
+        let handle = std::thread::Builder::new()
+            .name("worker".to_owned())
+            .spawn({
+                let shared = shared.clone();
+                move || {
+                    // threads spawned by the jobs belong to the program too
+                    let _entered = supervisor.as_ref().map(Supervisor::enter);
+                    while let Some((version, job)) = shared.next_job() {
+                        let context = JobContext {
+                            version,
+                            current_version: shared.version.clone(),
+                        };
+                        let msg = run(job, &context);
+                        if let Some(msg) = msg.filter(|_| !context.is_cancelled()) {
+                            send_msg(msg);
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn worker thread");
+        *shared.handle.lock().unwrap() = Some(handle);
+
+        let stop: Arc<dyn Stop> = shared.clone();
+        Supervisor::add_worker(Arc::downgrade(&stop));
+        Self {
+            shared,
+            _msg: std::marker::PhantomData,
+        }
+    }
+
+    /// Queues the job, replacing the pending one if the worker is coalescing
+    pub fn submit(&self, job: Job) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.stopped {
+            return;
+        }
+        if queue.coalesce {
+            queue.jobs.clear();
+        }
+        queue.jobs.push_back((self.version(), job));
+        self.shared.condvar.notify_one();
+    }
+
+    /// Drops the pending jobs and starts a new version, so that the result of the running
+    /// job is not sent either
+    pub fn cancel(&self) {
+        // under the lock, so that a job submitted concurrently is either dropped or queued with
+        // the new version
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.jobs.clear();
+        self.shared.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// The current version, increased by every [`Self::cancel`]. Messages can carry the
+    /// version of their job, to tell apart results that were already sent when it was
+    /// cancelled.
+    pub fn version(&self) -> u64 {
+        self.shared.version.load(Ordering::Acquire)
+    }
+
+    /// Cancels everything and waits until the thread ends, at most [`thread::EXIT_TIMEOUT`]
+    pub fn stop(&self) {
+        self.shared.stop(Instant::now() + thread::EXIT_TIMEOUT);
+    }
+}
+
+impl<Job> Shared<Job> {
+    // blocks until there is a job, `None` once the worker is stopped
+    fn next_job(&self) -> Option<(u64, Job)> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if queue.stopped {
+                return None;
+            }
+            if let Some(job) = queue.jobs.pop_front() {
+                return Some(job);
+            }
+            queue = self.condvar.wait(queue).unwrap();
+        }
+    }
+}
+
+impl<Job, Msg> Debug for Worker<Job, Msg> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let queue = self.shared.queue.lock().unwrap();
+        f.debug_struct("Worker")
+            .field("version", &self.shared.version.load(Ordering::Relaxed))
+            .field("pending", &queue.jobs.len())
+            .field("stopped", &queue.stopped)
+            .finish()
+    }
+}
+
+impl<Job, Msg> Drop for Worker<Job, Msg> {
+    fn drop(&mut self) {
+        self.shared.stop(Instant::now() + thread::EXIT_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    type TestWorker = Worker<u32, (u32, u64)>;
+
+    // a worker whose job 0 blocks until the returned sender is used, and which sends the jobs
+    // with their versions
+    fn worker(coalesce: bool) -> (TestWorker, mpsc::Sender<()>, mpsc::Receiver<(u32, u64)>) {
+        let (msg_tx, msg_rx) = mpsc::channel();
+        let (gate_tx, gate_rx) = mpsc::channel();
+        let send_msg: SendMsgFn<(u32, u64)> = Box::new(move |msg| {
+            let _ = msg_tx.send(msg);
+        });
+        let run = move |job, context: &JobContext| {
+            if job == 0 {
+                gate_rx.recv_timeout(TIMEOUT).unwrap();
+            }
+            Some((job, context.version()))
+        };
+        let worker = if coalesce {
+            Worker::coalescing(send_msg, run)
+        } else {
+            Worker::new(send_msg, run)
+        };
+        (worker, gate_tx, msg_rx)
+    }
+
+    // waits until the worker takes the blocking job 0
+    fn start_blocking_job(worker: &TestWorker) {
+        worker.submit(0);
+        let start = Instant::now();
+        while !worker.shared.queue.lock().unwrap().jobs.is_empty() {
+            assert!(start.elapsed() < TIMEOUT, "the worker didn't take the job");
+            std::thread::yield_now();
+        }
+    }
+
+    fn received(msg_rx: &mpsc::Receiver<(u32, u64)>) -> Vec<(u32, u64)> {
+        let mut received = Vec::new();
+        while let Ok(msg) = msg_rx.recv_timeout(Duration::from_millis(100)) {
+            received.push(msg);
+        }
+        received
+    }
+
+    #[test]
+    fn runs_all_jobs_in_order() {
+        let (worker, gate, msg_rx) = worker(false);
+        start_blocking_job(&worker);
+        worker.submit(1);
+        worker.submit(2);
+        gate.send(()).unwrap();
+        assert_eq!(received(&msg_rx), [(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn coalescing_runs_the_latest_job() {
+        let (worker, gate, msg_rx) = worker(true);
+        start_blocking_job(&worker);
+        worker.submit(1);
+        worker.submit(2);
+        worker.submit(3);
+        gate.send(()).unwrap();
+        assert_eq!(received(&msg_rx), [(0, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn cancel_drops_pending_jobs_and_results() {
+        let (worker, gate, msg_rx) = worker(false);
+        start_blocking_job(&worker);
+        worker.submit(1);
+        worker.cancel();
+        assert_eq!(worker.version(), 1);
+        gate.send(()).unwrap();
+        worker.submit(2);
+        assert_eq!(received(&msg_rx), [(2, 1)]);
+    }
+
+    #[test]
+    fn stopped_worker_ignores_jobs() {
+        let (worker, _gate, msg_rx) = worker(false);
+        worker.stop();
+        worker.submit(1);
+        assert!(received(&msg_rx).is_empty());
+        assert!(worker.shared.handle.lock().unwrap().is_none());
+    }
+}